@@ -22,10 +22,16 @@ pub enum SimulationMode {
 pub struct BindingConfig {
     pub simulation: SimulationMode,
     pub scenarios: JsoncObj,
+    pub fragments: Option<JsoncObj>,
     pub target: Option<&'static str>,
     pub loop_reset: bool,
     pub delay_conf: InjectorDelayConf,
     pub retry_conf: InjectorRetryConf,
+    pub tolerance: MatchTolerance,
+    pub tags: Vec<String>,
+    pub delay_model: Option<InjectorDelayModel>,
+    pub clock_conf: ClockConf,
+    pub clock_mode: Option<ClockMode>,
 }
 
 struct ApiInjectorCtx {
@@ -45,12 +51,28 @@ impl AfbApiControls for ApiInjectorCtx {
             injector: self.injector,
             event: None,
             api: api.get_apiv4(),
+            tags: None,
         };
-        job_scenario_exec(&param)?;
+        // TAP result is printed whatever the run status, a failed run exits with error
+        let status = job_scenario_exec(&param);
         let result = self.injector.get_result()?;
         println!("{:#}", result);
-        afb_log_msg!(Notice, api, "scenario={} exit", self.injector.get_uid());
-        std::process::exit(0);
+        match status {
+            Ok(()) => {
+                afb_log_msg!(Notice, api, "scenario={} exit", self.injector.get_uid());
+                std::process::exit(0);
+            }
+            Err(error) => {
+                afb_log_msg!(
+                    Error,
+                    api,
+                    "scenario={} fail error:{}",
+                    self.injector.get_uid(),
+                    error
+                );
+                std::process::exit(1);
+            }
+        }
     }
 
     // mandatory unsed declaration
@@ -64,6 +86,13 @@ impl AfbApiControls for ApiInjectorCtx {
 pub fn binding_init(_rootv4: AfbApiV4, jconf: JsoncObj) -> Result<&'static AfbApi, AfbError> {
     //afb_log_msg!(Info, rootv4, "config:{}", jconf);
 
+    // report unknown or mistyped fields, strict mode refuses to load
+    let schema = match env::var("SCENARIO_SCHEMA") {
+        Err(_) => jconf.default::<String>("schema", "warn".to_string())?,
+        Ok(value) => value,
+    };
+    schema_validate(&jconf, SchemaMode::parse(&schema)?)?;
+
     let uid = jconf.default("uid", "iso15118-simu")?;
     let api = jconf.default("api", uid)?;
     let info = jconf.default("info", "")?;
@@ -97,24 +126,78 @@ pub fn binding_init(_rootv4: AfbApiV4, jconf: JsoncObj) -> Result<&'static AfbAp
         );
     }
 
+    // transactions shared between scenarios with {"include": "fragment-uid"}
+    let fragments = jconf.optional::<JsoncObj>("fragments")?;
+    if let Some(jfragments) = &fragments {
+        if !jfragments.is_type(Jtype::Object) {
+            return afb_error!(
+                "simu-binding-config",
+                "fragments should be an object of named transaction arrays"
+            );
+        }
+    }
 
-    let delay_conf = match jconf.optional::<JsoncObj>("delay")? {
+    // replay the same trace as a fast test or with its recorded timing
+    let delay_model = match env::var("SCENARIO_DELAY_MODEL") {
+        Err(_) => None,
+        Ok(value) => Some(InjectorDelayModel::parse(&value)?),
+    };
+
+    let mut delay_conf = match jconf.optional::<JsoncObj>("delay")? {
         None => InjectorDelayConf::default(),
-        Some(jretry) => InjectorDelayConf::from_jsonc(jretry)?,
+        Some(jretry) => InjectorDelayConf::from_jsonc(jretry, &InjectorDelayConf::default())?,
     };
 
+    if let Some(model) = delay_model {
+        delay_conf.model = model;
+    }
+
     let retry_conf = match jconf.optional::<JsoncObj>("retry")? {
         None => InjectorRetryConf::default(),
-        Some(jretry) => InjectorRetryConf::from_jsonc(jretry, &delay_conf)?,
+        Some(jretry) => {
+            InjectorRetryConf::from_jsonc(jretry, &InjectorRetryConf::default(), &delay_conf)?
+        }
+    };
+
+    // virtual or accelerated clock skips delays and retries waiting time
+    let clock_mode = match env::var("SCENARIO_CLOCK") {
+        Err(_) => None,
+        Ok(value) => Some(ClockMode::parse(&value)?),
+    };
+
+    let mut clock_conf = match jconf.optional::<JsoncObj>("clock")? {
+        None => ClockConf::default(),
+        Some(jclock) => ClockConf::from_jsonc(jclock, &ClockConf::default())?,
+    };
+
+    if let Some(mode) = clock_mode {
+        clock_conf.mode = mode;
+    }
+
+    let tolerance = match jconf.optional::<JsoncObj>("tolerance")? {
+        None => MatchTolerance::default(),
+        Some(jtolerance) => MatchTolerance::from_jsonc(jtolerance)?,
+    };
+
+    // only run scenarios and transactions matching one of SCENARIO_TAGS
+    let tags = match env::var("SCENARIO_TAGS") {
+        Err(_) => Vec::new(),
+        Ok(value) => tags_from_str(&value),
     };
 
     let config = BindingConfig {
         simulation,
         scenarios: scenarios.clone(),
+        fragments,
         target,
         loop_reset,
         delay_conf,
         retry_conf,
+        tolerance,
+        tags,
+        delay_model,
+        clock_conf,
+        clock_mode,
     };
     // create an register frontend api and register init session callback
     let api = AfbApi::new(api).set_info(info);
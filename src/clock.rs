@@ -0,0 +1,218 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use serde_json::Value;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time;
+
+const DEFAULT_CLOCK_FACTOR: f64 = 10.0; // accelerated clock runs 10 times faster
+
+// how delays, retries and scenario deadline consume time
+#[derive(Clone, Copy, PartialEq)]
+pub enum ClockMode {
+    Real,
+    Accelerated,
+    Virtual,
+}
+
+impl ClockMode {
+    pub fn parse(mode: &str) -> Result<Self, AfbError> {
+        match mode.to_lowercase().as_str() {
+            "real" => Ok(ClockMode::Real),
+            "accelerated" => Ok(ClockMode::Accelerated),
+            "virtual" => Ok(ClockMode::Virtual),
+            other => afb_error!(
+                "injector-clock-mode",
+                "expected mode:'real'|'accelerated'|'virtual' got:{}",
+                other
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ClockConf {
+    pub mode: ClockMode,
+    pub factor: f64,
+    pub field: Option<&'static str>,
+}
+
+impl ClockConf {
+    pub fn default() -> Self {
+        Self {
+            mode: ClockMode::Real,
+            factor: DEFAULT_CLOCK_FACTOR,
+            field: None,
+        }
+    }
+
+    // missing fields are inherited from parent level (binding -> scenario)
+    pub fn from_jsonc(jsonc: JsoncObj, parent: &Self) -> Result<Self, AfbError> {
+        let mode = match jsonc.optional::<&str>("mode")? {
+            Some(value) => ClockMode::parse(value)?,
+            None => parent.mode,
+        };
+        let field = match jsonc.optional::<&'static str>("field")? {
+            Some(value) => Some(value),
+            None => parent.field,
+        };
+        let clock_conf = Self {
+            mode,
+            factor: jsonc.default("factor", parent.factor)?,
+            field,
+        };
+        if clock_conf.factor < 1.0 {
+            return afb_error!("injector-clock-mode", "expected factor>=1 got:{}", jsonc);
+        }
+        Ok(clock_conf)
+    }
+}
+
+// scenario clock, real time plus the part of every sleep that was skipped.
+// Responses are still awaited in real time as targets run on the wall clock.
+// A scenario stop cancels the clock, interrupting sleeps and pending waits.
+pub struct InjectorClock {
+    conf: ClockConf,
+    offset: Mutex<time::Duration>,
+    cancel: (Mutex<bool>, Condvar),
+    pending: Mutex<Vec<Watchdog>>,
+}
+
+pub type Clock = Arc<InjectorClock>;
+
+impl InjectorClock {
+    pub fn new(conf: ClockConf) -> Clock {
+        Arc::new(InjectorClock {
+            conf,
+            offset: Mutex::new(time::Duration::ZERO),
+            cancel: (Mutex::new(false), Condvar::new()),
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    // called at scenario start
+    pub fn reset(&self) {
+        if let Ok(mut offset) = self.offset.lock() {
+            *offset = time::Duration::ZERO;
+        }
+        self.resume();
+    }
+
+    // teardown still runs after a stop
+    pub fn resume(&self) {
+        if let Ok(mut cancelled) = self.cancel.0.lock() {
+            *cancelled = false;
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        match self.cancel.0.lock() {
+            Ok(cancelled) => *cancelled,
+            Err(_) => false,
+        }
+    }
+
+    // wake up sleeping job and abort every pending wait
+    pub fn cancel(&self) {
+        let (lock, cvar) = &self.cancel;
+        if let Ok(mut cancelled) = lock.lock() {
+            *cancelled = true;
+            cvar.notify_all();
+        }
+        if let Ok(pending) = self.pending.lock() {
+            pending.iter().for_each(clock_abort_wait);
+        }
+    }
+
+    // register a reply wait, aborted at once when scenario is already stopped
+    pub fn arm(&self, semaphore: &Watchdog) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.push(semaphore.clone());
+        }
+        if self.is_cancelled() {
+            clock_abort_wait(semaphore);
+        }
+    }
+
+    pub fn disarm(&self, semaphore: &Watchdog) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.retain(|value| !Arc::ptr_eq(value, semaphore));
+        }
+    }
+
+    // time skipped by accelerated or virtual sleeps since scenario start
+    pub fn get_offset(&self) -> time::Duration {
+        match self.offset.lock() {
+            Ok(offset) => *offset,
+            Err(_) => time::Duration::ZERO,
+        }
+    }
+
+    pub fn now(&self) -> time::Instant {
+        time::Instant::now() + self.get_offset()
+    }
+
+    // time left before a clock deadline, None when already expired
+    pub fn remaining(&self, deadline: Option<time::Instant>) -> Option<time::Duration> {
+        match deadline {
+            None => Some(time::Duration::MAX),
+            Some(value) => value
+                .checked_duration_since(self.now())
+                .filter(|remaining| !remaining.is_zero()),
+        }
+    }
+
+    pub fn sleep(&self, delay: time::Duration) {
+        let real = match self.conf.mode {
+            ClockMode::Real => delay,
+            ClockMode::Accelerated => delay.div_f64(self.conf.factor),
+            ClockMode::Virtual => time::Duration::ZERO,
+        };
+        let (lock, cvar) = &self.cancel;
+        if let Ok(cancelled) = lock.lock() {
+            let _ = cvar.wait_timeout_while(cancelled, real, |cancelled| !*cancelled);
+        }
+        if let Ok(mut offset) = self.offset.lock() {
+            *offset += delay - real;
+        }
+    }
+
+    // optional clock.field added to object queries, lets target shift its own time
+    pub fn query_offset(&self, jquery: JsoncObj) -> Result<JsoncObj, AfbError> {
+        let field = match self.conf.field {
+            None => return Ok(jquery),
+            Some(value) => value,
+        };
+        match jsonc_to_value(&jquery)? {
+            Value::Object(mut map) => {
+                map.insert(
+                    field.to_string(),
+                    Value::from(self.get_offset().as_millis() as u64),
+                );
+                value_to_jsonc(&Value::Object(map))
+            }
+            _ => Ok(jquery),
+        }
+    }
+}
+
+fn clock_abort_wait(semaphore: &Watchdog) {
+    let (lock, cvar) = &**semaphore;
+    if let Ok(mut reply) = lock.lock() {
+        if let SimulationStatus::Pending = reply.status {
+            reply.status = SimulationStatus::Aborted;
+            cvar.notify_all();
+        }
+    }
+}
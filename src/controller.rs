@@ -12,9 +12,10 @@
 
 use crate::prelude::*;
 use afbv4::prelude::*;
+use serde_json::Value;
 use std::cell::Cell;
 use std::sync::{Mutex, MutexGuard};
-use std::{thread, time};
+use std::time;
 
 const DEFAULT_MIN_TIMEOUT: u64 = 10; // scenario minimal timeout in seconds
 const DEFAULT_CALL_TIMEOUT: u64 = 1000; // call_sync 1s default timeout
@@ -22,40 +23,183 @@ const DEFAULT_CALL_DELAY: u64 = 100; // call_sync 100ms delay
 const DEFAULT_DELAY_PERCENT: u64 = 10; // reduce delay by 10
 const DEFAULT_DELAY_MIN: u64 = 50; // reduce delay by 10
 const DEFAULT_DELAY_MAX: u64 = 100; // reduce delay by 10
+const DEFAULT_DELAY_JITTER: f64 = 0.1; // jitter model +/-10% around recorded delay
+const DEFAULT_REPEAT_MAX: u32 = 100; // repeat-until max iterations
+const DEFAULT_BRANCH_MAX: u32 = 1000; // on_success/on_fail max jumps per scenario run
+const DEFAULT_WATCHDOG_MARGIN: u64 = 30; // job watchdog delay after scenario deadline in seconds, leaves room for teardown
+
+// how recorded transaction delays are replayed
+#[derive(Clone, Copy, PartialEq)]
+pub enum InjectorDelayModel {
+    Real,
+    Scaled,
+    Fixed,
+    None,
+    Jitter,
+}
+
+impl InjectorDelayModel {
+    pub fn parse(model: &str) -> Result<Self, AfbError> {
+        match model.to_lowercase().as_str() {
+            "real" => Ok(InjectorDelayModel::Real),
+            "scaled" => Ok(InjectorDelayModel::Scaled),
+            "fixed" => Ok(InjectorDelayModel::Fixed),
+            "none" => Ok(InjectorDelayModel::None),
+            "jitter" => Ok(InjectorDelayModel::Jitter),
+            other => afb_error!(
+                "injector-delay-model",
+                "expected model:'real'|'scaled'|'fixed'|'none'|'jitter' got:{}",
+                other
+            ),
+        }
+    }
+}
 
 #[derive(Clone, Copy)]
 pub struct InjectorDelayConf {
+    pub model: InjectorDelayModel,
     pub percent: u64,
     pub min: u64,
     pub max: u64,
+    pub value: u64,
+    pub jitter: f64,
 }
 
 impl InjectorDelayConf {
     pub fn default() -> Self {
         Self {
+            model: InjectorDelayModel::Scaled,
             percent: DEFAULT_DELAY_PERCENT,
             min: DEFAULT_DELAY_MIN,
             max: DEFAULT_DELAY_MAX,
+            value: DEFAULT_CALL_DELAY,
+            jitter: DEFAULT_DELAY_JITTER,
         }
     }
-    pub fn from_jsonc(jsonc: JsoncObj) -> Result<Self, AfbError> {
-        Ok(Self {
-            percent: jsonc.default("percent", DEFAULT_DELAY_PERCENT)?,
-            min: jsonc.default("min", DEFAULT_DELAY_MIN)?,
-            max: jsonc.default("max", DEFAULT_DELAY_MAX)?,
-        })
+    // missing fields are inherited from parent level (binding -> scenario)
+    pub fn from_jsonc(jsonc: JsoncObj, parent: &Self) -> Result<Self, AfbError> {
+        let model = match jsonc.optional::<&str>("model")? {
+            Some(value) => InjectorDelayModel::parse(value)?,
+            None => parent.model,
+        };
+        let delay_conf = Self {
+            model,
+            percent: jsonc.default("percent", parent.percent)?,
+            min: jsonc.default("min", parent.min)?,
+            max: jsonc.default("max", parent.max)?,
+            value: jsonc.default("value", parent.value)?,
+            jitter: jsonc.default("jitter", parent.jitter)?,
+        };
+        if !(0.0..=1.0).contains(&delay_conf.jitter) {
+            return afb_error!(
+                "injector-delay-model",
+                "expected jitter within 0..1 got:{}",
+                jsonc
+            );
+        }
+        Ok(delay_conf)
+    }
+
+    // random spread applied at run time around recorded delay, 0 when model is not jitter
+    pub fn get_jitter(&self) -> f64 {
+        match self.model {
+            InjectorDelayModel::Jitter => self.jitter,
+            _ => 0.0,
+        }
+    }
+
+    // share of recorded timing kept by the model, used to size scenario timeout
+    pub fn get_percent(&self) -> u64 {
+        match self.model {
+            InjectorDelayModel::Real | InjectorDelayModel::Jitter => 100,
+            _ => self.percent,
+        }
     }
 
     pub fn get_duration(&self, millis: u64) -> time::Duration {
+        let millis = match self.model {
+            InjectorDelayModel::Real | InjectorDelayModel::Jitter => millis,
+            InjectorDelayModel::Fixed => self.value,
+            InjectorDelayModel::None => 0,
+            InjectorDelayModel::Scaled => self.get_scaled(millis),
+        };
+        time::Duration::from_millis(millis)
+    }
+
+    fn get_scaled(&self, millis: u64) -> u64 {
         let delay = millis * self.percent / 100;
-        let delay = if delay > self.max {
+        if delay > self.max {
             self.max
         } else if delay < self.min {
             self.min
         } else {
             delay
+        }
+    }
+}
+
+// failure classes worth a new attempt, default only retries on error
+#[derive(Clone, Copy)]
+pub struct InjectorRetryOn {
+    pub error: bool,
+    pub timeout: bool,
+    pub mismatch: bool,
+}
+
+impl InjectorRetryOn {
+    pub fn default() -> Self {
+        Self {
+            error: true,
+            timeout: false,
+            mismatch: false,
+        }
+    }
+
+    // retry.on config: "timeout" | ["error", "timeout", "mismatch"]
+    pub fn from_jsonc(jon: &JsoncObj) -> Result<Self, AfbError> {
+        let mut retry_on = Self {
+            error: false,
+            timeout: false,
+            mismatch: false,
         };
-        time::Duration::from_millis(delay)
+        for name in tags_from_jsonc(jon)? {
+            match name.as_str() {
+                "error" => retry_on.error = true,
+                "timeout" => retry_on.timeout = true,
+                "mismatch" => retry_on.mismatch = true,
+                other => {
+                    return afb_error!(
+                        "injector-retry-on",
+                        "expected retry on:'error'|'timeout'|'mismatch' got:{}",
+                        other
+                    )
+                }
+            }
+        }
+        Ok(retry_on)
+    }
+}
+
+// delay growth between attempts
+#[derive(Clone, Copy)]
+pub enum InjectorBackoff {
+    Fixed,
+    Linear,
+    Exponential,
+}
+
+impl InjectorBackoff {
+    pub fn parse(backoff: &str) -> Result<Self, AfbError> {
+        match backoff.to_lowercase().as_str() {
+            "fixed" => Ok(InjectorBackoff::Fixed),
+            "linear" => Ok(InjectorBackoff::Linear),
+            "exponential" => Ok(InjectorBackoff::Exponential),
+            other => afb_error!(
+                "injector-retry-backoff",
+                "expected backoff:'fixed'|'linear'|'exponential' got:{}",
+                other
+            ),
+        }
     }
 }
 
@@ -64,6 +208,12 @@ pub struct InjectorRetryConf {
     pub delay: time::Duration,
     pub timeout: time::Duration,
     pub count: u32,
+    pub on: InjectorRetryOn,
+    pub backoff: InjectorBackoff,
+    pub factor: f64,
+    pub max_delay: Option<time::Duration>,
+    pub jitter: f64,
+    pub budget: Option<time::Duration>,
 }
 
 impl InjectorRetryConf {
@@ -72,132 +222,730 @@ impl InjectorRetryConf {
             delay: time::Duration::from_millis(DEFAULT_CALL_DELAY),
             timeout: time::Duration::from_millis(DEFAULT_CALL_TIMEOUT),
             count: 1,
+            on: InjectorRetryOn::default(),
+            backoff: InjectorBackoff::Fixed,
+            factor: 2.0,
+            max_delay: None,
+            jitter: 0.0,
+            budget: None,
         }
     }
-    pub fn from_jsonc(jsonc: JsoncObj, delay_conf: &InjectorDelayConf) -> Result<Self, AfbError> {
-        Ok(Self {
-            delay: delay_conf.get_duration(jsonc.default("delay", DEFAULT_CALL_TIMEOUT)?),
-            timeout: time::Duration::from_millis(jsonc.default("timeout", DEFAULT_CALL_TIMEOUT)?),
-            count: jsonc.default("count", 1)?,
-        })
+    // missing fields are inherited from parent level (binding -> scenario -> transaction)
+    pub fn from_jsonc(
+        jsonc: JsoncObj,
+        parent: &Self,
+        delay_conf: &InjectorDelayConf,
+    ) -> Result<Self, AfbError> {
+        // delay models only replay recorded transaction delays, retry delay stays scaled
+        let delay = match jsonc.optional::<u64>("delay")? {
+            Some(value) => time::Duration::from_millis(delay_conf.get_scaled(value)),
+            None => parent.delay,
+        };
+        let timeout = match jsonc.optional::<u64>("timeout")? {
+            Some(value) => time::Duration::from_millis(value),
+            None => parent.timeout,
+        };
+        let on = match jsonc.optional::<JsoncObj>("on")? {
+            Some(jon) => InjectorRetryOn::from_jsonc(&jon)?,
+            None => parent.on,
+        };
+        let backoff = match jsonc.optional::<&str>("backoff")? {
+            Some(value) => InjectorBackoff::parse(value)?,
+            None => parent.backoff,
+        };
+        let max_delay = match jsonc.optional::<u64>("max_delay")? {
+            Some(value) => Some(time::Duration::from_millis(value)),
+            None => parent.max_delay,
+        };
+        let budget = match jsonc.optional::<u64>("budget")? {
+            Some(value) => Some(time::Duration::from_millis(value)),
+            None => parent.budget,
+        };
+        let retry = Self {
+            delay,
+            timeout,
+            count: jsonc.default("count", parent.count)?,
+            on,
+            backoff,
+            factor: jsonc.default("factor", parent.factor)?,
+            max_delay,
+            jitter: jsonc.default("jitter", parent.jitter)?,
+            budget,
+        };
+        if retry.factor < 0.0 || !(0.0..=1.0).contains(&retry.jitter) {
+            return afb_error!(
+                "injector-retry-backoff",
+                "expected factor>=0 and jitter within 0..1 got:{}",
+                jsonc
+            );
+        }
+        Ok(retry)
+    }
+
+    // delay before attempt+1, jitter draws from scenario generator to follow its seed
+    pub fn get_delay(&self, attempt: u32, generator: &Generator) -> time::Duration {
+        let delay = self.delay.as_secs_f64();
+        let delay = match self.backoff {
+            InjectorBackoff::Fixed => delay,
+            InjectorBackoff::Linear => delay * (1.0 + self.factor * attempt as f64),
+            InjectorBackoff::Exponential => delay * self.factor.powi(attempt as i32),
+        };
+        let delay = match generator.lock() {
+            Ok(mut generator) if self.jitter > 0.0 => {
+                delay * (1.0 + self.jitter * (2.0 * generator.next_f64() - 1.0))
+            }
+            _ => delay,
+        };
+        // exponential growth may overflow duration, bound it to one day
+        let delay = if delay.is_nan() {
+            time::Duration::ZERO
+        } else {
+            time::Duration::from_secs_f64(delay.clamp(0.0, 86400.0))
+        };
+        match self.max_delay {
+            Some(max) => delay.min(max),
+            None => delay,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct InjectorRepeat {
+    pub count: u32,
+    pub until: Option<JsoncObj>,
+    pub max: u32,
+}
+
+impl InjectorRepeat {
+    // repeat at least 'count' time, then until response matches 'until' with 'max' as upper bound
+    pub fn from_jsonc(uid: &str, jsonc: JsoncObj) -> Result<Self, AfbError> {
+        let count = jsonc.default("count", 1)?;
+        let until = jsonc.optional::<JsoncObj>("until")?;
+        let max = match until {
+            Some(_) => jsonc.default("max", DEFAULT_REPEAT_MAX)?,
+            None => jsonc.default("max", count)?,
+        };
+        if count == 0 || max < count {
+            return afb_error!(
+                "injector-repeat",
+                "uid:{} invalid repeat count:{} max:{}",
+                uid,
+                count,
+                max
+            );
+        }
+        Ok(Self { count, until, max })
+    }
+}
+
+// push step report to scenario subscribers, print it when running without event
+fn push_or_print(event: Option<&AfbEvent>, uid: &str, label: &str, jreply: &JsoncObj) {
+    match event {
+        Some(evt) => {
+            evt.push(jreply.clone());
+        }
+        None => {
+            println!("--[{}] {} {}", uid, label, jreply);
+        }
     }
 }
 
 fn spawn_one_transaction(
     api: AfbApiV4,
     transac: &mut InjectorEntry,
+    captures: &mut CaptureStore,
     event: Option<&AfbEvent>,
+    deadline: Option<time::Instant>,
 ) -> Result<(), AfbError> {
+    let repeat = match &transac.repeat {
+        None => {
+            transac.iterations = 1;
+            spawn_one_iteration(api, transac, captures, event, deadline)?;
+            return Ok(());
+        }
+        Some(value) => value.clone(),
+    };
+
+    transac.iterations = 0;
+    loop {
+        transac.iterations += 1;
+        let received = spawn_one_iteration(api, transac, captures, event, deadline)?;
+        if transac.iterations < repeat.count {
+            continue;
+        }
+
+        let until = match &repeat.until {
+            None => break,
+            Some(value) => value,
+        };
+        if let Some(received) = received {
+            if expect_match(
+                transac.uid,
+                &value_to_jsonc(&received)?,
+                until,
+                &transac.tolerance,
+            )
+            .is_ok()
+            {
+                break;
+            }
+        }
+
+        if transac.iterations >= repeat.max {
+            transac.status = SimulationStatus::fail(
+                transac.uid,
+                format!(
+                    "repeat until not reached after {} iterations",
+                    transac.iterations
+                ),
+            );
+            return afb_error!(
+                "job_transaction_cb",
+                "repeat until not reached for uid:{} iterations:{}",
+                transac.uid,
+                transac.iterations
+            );
+        }
+    }
+    Ok(())
+}
+
+// return last received response to check repeat exit condition
+fn spawn_one_iteration(
+    api: AfbApiV4,
+    transac: &mut InjectorEntry,
+    captures: &mut CaptureStore,
+    event: Option<&AfbEvent>,
+    deadline: Option<time::Instant>,
+) -> Result<Option<Value>, AfbError> {
     // send result as event
     let jreply = JsoncObj::new();
     jreply.add("uid", transac.uid)?;
+    jreply.add("target", transac.target)?;
     jreply.add("verb", transac.verb)?;
+    if transac.repeat.is_some() {
+        jreply.add("iteration", transac.iterations)?;
+    }
 
-    // initial request delay
-    thread::sleep(transac.delay);
+    // initial request delay, deadline and retry budget follow scenario clock
+    let clock = transac.clock.clone();
+    if let Some(remaining) = clock.remaining(deadline) {
+        clock.sleep(transac.get_delay().min(remaining));
+    }
 
+    let mut received = None;
+    let budget = transac.retry.budget.map(|budget| clock.now() + budget);
     for idx in 0..transac.retry.count {
+        // scenario stop interrupts delays and pending waits
+        if clock.is_cancelled() {
+            transac.status = SimulationStatus::Aborted;
+            break;
+        }
+        // scenario deadline bounds every request wait
+        let remaining = match clock.remaining(deadline) {
+            Some(value) => value,
+            None => {
+                transac.status = SimulationStatus::Timeout;
+                return afb_error!(transac.uid, "scenario deadline reached");
+            }
+        };
+        // as well as transaction retry budget
+        let remaining = match (budget, clock.remaining(budget)) {
+            (None, _) => remaining,
+            (Some(_), Some(value)) => remaining.min(value),
+            (Some(_), None) => {
+                transac.status = SimulationStatus::fail(
+                    transac.uid,
+                    format!("retry budget exhausted after {} attempts", idx),
+                );
+                break;
+            }
+        };
         transac.status = SimulationStatus::Pending;
-        transac.status = match injector_launch_transac(api, transac) {
-            Ok(value) => value,
-            Err(error) => {
-                // api/verb did not return
-                if idx < transac.retry.count {
-                    jreply.add("status", "SimulationStatus::Retry")?;
-                    match event {
-                        Some(evt) => {
-                            evt.push(jreply.clone());
-                        }
-                        None => {
-                            println!(
-                                "--[{}:{}] SimulationStatus::Retry {}",
-                                transac.uid, idx, jreply
-                            );
-                        }
+        let start = time::Instant::now();
+        let retry = match injector_launch_transac(api, transac, captures, remaining) {
+            Ok(reply) => {
+                let code = reply.code;
+                received = transac.set_reply(reply, start, captures);
+                match &transac.status {
+                    SimulationStatus::Done | SimulationStatus::Check => break,
+                    SimulationStatus::Aborted => break,
+                    SimulationStatus::Timeout => transac.retry.on.timeout,
+                    // target rejected the request vs invalid response values
+                    SimulationStatus::Fail(_) if code != 0 => transac.retry.on.error,
+                    SimulationStatus::Fail(_) => transac.retry.on.mismatch,
+                    _ => {
+                        return afb_error!(
+                            "job_transaction_cb",
+                            "unexpected status for uid:{} count:{}",
+                            transac.uid,
+                            idx
+                        )
                     }
-                    thread::sleep(transac.retry.delay);
-                    SimulationStatus::Retry
-                } else {
-                    jreply.add("error", error.to_jsonc()?)?;
-                    match event {
-                        Some(evt) => {
-                            evt.push(jreply.clone());
-                        }
-                        None => {
-                            println!(
-                                "--[{}:{}] SimulationStatus::Retry {}",
-                                transac.uid, idx, jreply
-                            );
-                        }
-                    }
-                    return afb_error!(transac.uid, "call_async fail {}", error);
                 }
             }
+            Err(error) => {
+                // api/verb did not return
+                transac.status = SimulationStatus::Fail(error);
+                transac.retry.on.error
+            }
         };
+
+        if !retry || idx + 1 >= transac.retry.count {
+            break;
+        }
+        let delay = transac.retry.get_delay(idx, &transac.generator);
+        if clock
+            .remaining(budget)
+            .is_some_and(|remaining| remaining <= delay)
+        {
+            break;
+        }
+        transac.status = SimulationStatus::Retry;
+        jreply.add("status", "SimulationStatus::Retry")?;
+        push_or_print(
+            event,
+            &format!("{}:{}", transac.uid, idx),
+            "SimulationStatus::Retry",
+            &jreply,
+        );
+        if let Some(remaining) = clock.remaining(deadline) {
+            clock.sleep(delay.min(remaining));
+        }
+    }
+
+    match &transac.status {
+        SimulationStatus::Fail(error) => {
+            jreply.add("status", "SimulationStatus::Fail")?;
+            jreply.add("error", error.to_jsonc()?)?;
+            push_or_print(event, transac.uid, "SimulationStatus::Fail", &jreply);
+            return afb_error!(
+                "job_transaction_cb",
+                "unexpected status for uid:{} count:{} error:{}",
+                transac.uid,
+                transac.retry.count,
+                error
+            );
+        }
+        SimulationStatus::Timeout => {
+            jreply.add("status", "SimulationStatus::Timeout")?;
+            push_or_print(event, transac.uid, "SimulationStatus::Timeout", &jreply);
+            return afb_error!(
+                "job_transaction_cb",
+                "no response for uid:{} count:{} timeout:{}ms",
+                transac.uid,
+                transac.retry.count,
+                transac.retry.timeout.as_millis()
+            );
+        }
+        SimulationStatus::Aborted => {
+            jreply.add("status", "SimulationStatus::Aborted")?;
+            push_or_print(event, transac.uid, "SimulationStatus::Aborted", &jreply);
+            return afb_error!(
+                "job_transaction_cb",
+                "uid:{} aborted by scenario stop",
+                transac.uid
+            );
+        }
+        _ => {}
+    }
+
+    let status = format!("{:?}", &transac.status);
+    jreply.add("status", &status)?;
+    if let Some(latency) = transac.latency {
+        jreply.add("latency", latency.as_millis() as u64)?;
+    }
+    push_or_print(event, transac.uid, &status, &jreply);
+    Ok(received)
+}
+
+pub struct JobScenarioParam {
+    pub api: AfbApiV4,
+    pub injector: &'static Injector,
+    pub event: Option<&'static AfbEvent>,
+    pub tags: Option<Vec<String>>,
+}
+
+// entries with an unmet depends_on are skipped and never sent
+fn skip_one_entry(
+    param: &JobScenarioParam,
+    state: &mut ScenarioState,
+    idx: usize,
+) -> Result<bool, AfbError> {
+    let reason = match (&state.entries[idx].skip, state.unmet_dependency(idx)) {
+        (Some(reason), _) => reason.clone(),
+        (None, Some(reason)) => reason,
+        (None, None) => return Ok(false),
+    };
+    let transac = &mut state.entries[idx];
+    let jreply = JsoncObj::new();
+    jreply.add("uid", transac.uid)?;
+    jreply.add("target", transac.target)?;
+    jreply.add("verb", transac.verb)?;
+    jreply.add("status", "Skip")?;
+    jreply.add("skip", reason.as_str())?;
+    push_or_print(param.event, transac.uid, "Skip", &jreply);
+    transac.status = SimulationStatus::Skip;
+    transac.skip = Some(reason);
+    Ok(true)
+}
+
+// dispatch every parallel branch before waiting for their responses
+fn spawn_parallel_entries(
+    param: &JobScenarioParam,
+    group: &InjectorParallel,
+) -> Result<(), AfbError> {
+    // state is not locked while waiting, a watchdog or stop may interrupt the run
+    let clock = &param.injector.clock;
+    let (mut entries, mut captures, deadline) = {
+        let mut state = param.injector.lock_state()?;
+        if clock.is_cancelled() {
+            return afb_error!(group.uid.as_str(), "scenario stopped");
+        }
+        if clock.remaining(state.deadline).is_none() {
+            return param
+                .injector
+                .set_timeout(&mut state, &group.uid, param.event);
+        }
+        for idx in group.start..group.end {
+            if !skip_one_entry(param, &mut state, idx)? {
+                state.entries[idx].status = SimulationStatus::Pending;
+            }
+        }
+        (
+            state.entries[group.start..group.end].to_vec(),
+            state.captures.clone(),
+            state.deadline,
+        )
+    };
+
+    // branches start together after the longest requested delay
+    let delay = entries
+        .iter()
+        .map(|transac| transac.get_delay())
+        .max()
+        .unwrap_or_default();
+    if let Some(remaining) = clock.remaining(deadline) {
+        clock.sleep(delay.min(remaining));
+    }
+    // stopped while waiting, branches are reported as aborted by kill_scenario
+    if clock.is_cancelled() {
+        return afb_error!(group.uid.as_str(), "scenario stopped");
+    }
+
+    let mut pending = Vec::new();
+    for transac in entries.iter_mut().filter(|transac| transac.skip.is_none()) {
+        transac.iterations = 1;
+        let semaphore = injector_new_watchdog();
+        let start = time::Instant::now();
+        // a failed dispatch fails its branch, already sent branches are still awaited
+        match injector_async_request(param.api, transac, &captures, semaphore.clone()) {
+            Ok(()) => pending.push(Some((semaphore, start))),
+            Err(error) => {
+                transac.status = SimulationStatus::Fail(error);
+                pending.push(None);
+            }
+        }
+    }
+
+    let mut failed = 0;
+    for (transac, sent) in entries
+        .iter_mut()
+        .filter(|transac| transac.skip.is_none())
+        .zip(pending)
+    {
+        if let Some((semaphore, start)) = sent {
+            let timeout =
+                (start + transac.retry.timeout).saturating_duration_since(time::Instant::now());
+            let reply = match clock.remaining(deadline) {
+                Some(remaining) => injector_wait_reply(&semaphore, timeout.min(remaining), clock),
+                None => injector_wait_reply(&semaphore, time::Duration::ZERO, clock),
+            };
+            transac.set_reply(reply, start, &mut captures);
+        }
+
+        let status = format!("{:?}", &transac.status);
+        let jreply = JsoncObj::new();
+        jreply.add("uid", transac.uid)?;
+        jreply.add("target", transac.target)?;
+        jreply.add("verb", transac.verb)?;
+        jreply.add("parallel", group.uid.as_str())?;
+        jreply.add("status", status.as_str())?;
+        if let Some(latency) = transac.latency {
+            jreply.add("latency", latency.as_millis() as u64)?;
+        }
         match &transac.status {
-            SimulationStatus::Done | SimulationStatus::Check => break,
+            SimulationStatus::Done | SimulationStatus::Check => {}
             SimulationStatus::Fail(error) => {
-                // api/verb return invalid values
                 jreply.add("error", error.to_jsonc()?)?;
-                match event {
-                    Some(evt) => {
-                        evt.push(jreply.clone());
-                    }
-                    None => {
-                        println!("--[{}] SimulationStatus::Fail {}", transac.uid, jreply);
-                    }
-                }
-                break;
-            }
-            SimulationStatus::Retry => {}
-            _ => {
-                return afb_error!(
-                    "job_transaction_cb",
-                    "unexpected status for uid:{} count:{}",
-                    transac.uid,
-                    idx
-                )
+                failed += 1;
             }
+            _ => failed += 1,
+        }
+        push_or_print(param.event, transac.uid, &status, &jreply);
+    }
+
+    {
+        let mut state = param.injector.lock_state()?;
+        state.captures = captures;
+        for (idx, transac) in entries.into_iter().enumerate() {
+            state.entries[group.start + idx] = transac;
+        }
+        if clock.remaining(deadline).is_none() {
+            return param
+                .injector
+                .set_timeout(&mut state, &group.uid, param.event);
         }
     }
 
-    if let SimulationStatus::Fail(error) = &transac.status {
+    // combined status of joined branches
+    let status = if failed == 0 { "Done" } else { "Fail" };
+    let jreply = JsoncObj::new();
+    jreply.add("uid", group.uid.as_str())?;
+    jreply.add("status", status)?;
+    jreply.add("branches", group.end - group.start)?;
+    jreply.add("failed", failed)?;
+    push_or_print(param.event, &group.uid, status, &jreply);
+
+    if failed > 0 {
         return afb_error!(
             "job_transaction_cb",
-            "unexpected status for uid:{} count:{} error:{}",
-            transac.uid,
-            transac.retry.count,
-            error
+            "parallel uid:{} failed branches:{}/{}",
+            group.uid,
+            failed,
+            group.end - group.start
         );
     }
+    Ok(())
+}
 
-    let status = format!("{:?}", &transac.status);
-    jreply.add("status", &status)?;
-    match event {
-        Some(evt) => {
-            evt.push(jreply.clone());
-        }
-        None => {
-            println!("--[{}] {} {}", transac.uid, &status, jreply);
+// run entries[start..end] in sequence, parallel groups are dispatched together
+fn spawn_block_entries(param: &JobScenarioParam, start: usize, end: usize) -> Result<(), AfbError> {
+    let mut idx = start;
+    while idx < end {
+        match param.injector.get_parallel(idx) {
+            Some(group) => {
+                spawn_parallel_entries(param, group)?;
+                idx = group.end;
+            }
+            None => {
+                spawn_one_entry(param, idx)?;
+                idx += 1;
+            }
         }
     }
     Ok(())
 }
 
-pub struct JobScenarioParam {
-    pub api: AfbApiV4,
-    pub injector: &'static Injector,
-    pub event: Option<&'static AfbEvent>,
+// run one entry and return the branch to follow when on_success/on_fail is defined
+fn spawn_one_entry(
+    param: &JobScenarioParam,
+    idx: usize,
+) -> Result<Option<InjectorBranch>, AfbError> {
+    // state is not locked while waiting, a watchdog or stop may interrupt the run
+    let clock = &param.injector.clock;
+    let (mut transac, mut captures, deadline) = {
+        let mut state = param.injector.lock_state()?;
+        if clock.is_cancelled() {
+            return afb_error!(state.entries[idx].uid, "scenario stopped");
+        }
+        if clock.remaining(state.deadline).is_none() {
+            let uid = state.entries[idx].uid;
+            state.entries[idx].skip = Some("scenario timeout".to_string());
+            param.injector.set_timeout(&mut state, uid, param.event)?;
+        }
+        if skip_one_entry(param, &mut state, idx)? {
+            return Ok(None);
+        }
+        state.entries[idx].status = SimulationStatus::Pending;
+        (
+            state.entries[idx].clone(),
+            state.captures.clone(),
+            state.deadline,
+        )
+    };
+    let result = spawn_one_transaction(
+        param.api,
+        &mut transac,
+        &mut captures,
+        param.event,
+        deadline,
+    );
+
+    let mut state = param.injector.lock_state()?;
+    let state = &mut *state;
+    state.captures = captures;
+    state.entries[idx] = transac;
+
+    // scenario timeout ignores on_fail branches
+    if result.is_err() && clock.remaining(deadline).is_none() {
+        let uid = state.entries[idx].uid;
+        param.injector.set_timeout(state, uid, param.event)?;
+    }
+    let transac = &state.entries[idx];
+    let branch = match result {
+        Ok(()) => transac.on_success,
+        // scenario stop ignores on_fail branches
+        Err(error) if clock.is_cancelled() => return Err(error),
+        Err(error) => match transac.on_fail {
+            None => return Err(error),
+            Some(branch) => Some(branch),
+        },
+    };
+
+    if let Some(branch) = branch {
+        let label = match branch {
+            InjectorBranch::Goto(target) => format!("goto:{}", state.entries[target].uid),
+            InjectorBranch::Block(block) => format!("block:{}", param.injector.blocks[block].name),
+        };
+        let transac = &mut state.entries[idx];
+        let jreply = JsoncObj::new();
+        jreply.add("uid", transac.uid)?;
+        jreply.add("target", transac.target)?;
+        jreply.add("verb", transac.verb)?;
+        jreply.add("status", format!("{:?}", &transac.status).as_str())?;
+        jreply.add("branch", label.as_str())?;
+        push_or_print(param.event, transac.uid, &label, &jreply);
+        transac.branch = Some(label);
+    }
+    Ok(branch)
+}
+
+// teardown always runs every entry, first failure is returned
+fn spawn_teardown_entries(param: &JobScenarioParam) -> Result<(), AfbError> {
+    let teardown = match &param.injector.teardown {
+        None => return Ok(()),
+        Some(value) => value,
+    };
+
+    // teardown still runs after a scenario stop, the job watchdog should not restart it
+    param.injector.clock.resume();
+    param.injector.lock_state()?.teardown = true;
+    let mut status = Ok(());
+    let mut idx = teardown.start;
+    while idx < teardown.end {
+        let result = match param.injector.get_parallel(idx) {
+            Some(group) => {
+                idx = group.end;
+                spawn_parallel_entries(param, group)
+            }
+            None => {
+                idx += 1;
+                spawn_one_entry(param, idx - 1).map(|_| ())
+            }
+        };
+        if let (Err(error), true) = (result, status.is_ok()) {
+            status = Err(error);
+        }
+    }
+    status
 }
 
 pub fn job_scenario_exec(param: &JobScenarioParam) -> Result<(), AfbError> {
-    // loop on scenario transactions
-    for idx in 0..param.injector.count {
+    // previous run status and captures should not leak into this one
+    {
+        let tags = match &param.tags {
+            Some(value) => value,
+            None => &param.injector.tags,
+        };
         let mut state = param.injector.lock_state()?;
-        let transac = &mut state.entries[idx];
-        spawn_one_transaction(param.api, transac, param.event)?;
+        state.captures.clear();
+        param.injector.clock.reset();
+        state.deadline = Some(param.injector.clock.now() + param.injector.timeout);
+        if let Ok(mut generator) = param.injector.generator.lock() {
+            generator.reset();
+        }
+        state.verdict = None;
+        state.teardown = false;
+        for transac in state.entries.iter_mut() {
+            transac.status = SimulationStatus::Skip;
+            transac.branch = None;
+            transac.latency = None;
+            transac.skip = if tags_selected(&transac.tags, tags) {
+                None
+            } else {
+                Some(format!("tags:{} not selected", transac.tags.join(",")))
+            };
+        }
     }
-    Ok(())
+
+    let (status, reason) = match &param.injector.setup {
+        None => (Ok(()), "not executed"),
+        Some(setup) => match spawn_block_entries(param, setup.start, setup.end) {
+            Ok(()) => (Ok(()), "not executed"),
+            Err(error) => (Err(error), "setup failed"),
+        },
+    };
+    let (status, reason) = match status {
+        Ok(()) => match job_scenario_main(param) {
+            Ok(()) => (Ok(()), reason),
+            Err(error) => (Err(error), "scenario aborted"),
+        },
+        Err(error) => (Err(error), reason),
+    };
+
+    // teardown runs whatever the scenario status and is not bounded by scenario deadline
+    let reason = {
+        let mut state = param.injector.lock_state()?;
+        state.deadline = None;
+        match state.verdict {
+            _ if param.injector.clock.is_cancelled() => "scenario stopped",
+            Some(_) => "scenario timeout",
+            None => reason,
+        }
+    };
+    let teardown = spawn_teardown_entries(param);
+    param.injector.set_skip_reason(reason)?;
+    status?;
+    teardown
+}
+
+fn job_scenario_main(param: &JobScenarioParam) -> Result<(), AfbError> {
+    // loop on scenario transactions, following on_success/on_fail branches
+    let mut status = Ok(());
+    let mut idx = 0;
+    let mut branches = 0;
+    while idx < param.injector.count {
+        let (next, result) = match param.injector.get_parallel(idx) {
+            Some(group) => (
+                group.end,
+                spawn_parallel_entries(param, group).map(|_| None),
+            ),
+            None => (idx + 1, spawn_one_entry(param, idx)),
+        };
+        let branch = match result {
+            Ok(value) => value,
+            Err(error) => {
+                param.injector.failure_policy(&mut status, error)?;
+                None
+            }
+        };
+        idx = match branch {
+            None => next,
+            Some(branch) => {
+                branches += 1;
+                if branches > DEFAULT_BRANCH_MAX {
+                    return afb_error!(
+                        "job_scenario_exec",
+                        "scenario:{} too many branches (max:{})",
+                        param.injector.uid,
+                        DEFAULT_BRANCH_MAX
+                    );
+                }
+                match branch {
+                    InjectorBranch::Goto(target) => target,
+                    InjectorBranch::Block(block) => {
+                        let block = &param.injector.blocks[block];
+                        if let Err(error) = spawn_block_entries(param, block.start, block.end) {
+                            param.injector.failure_policy(&mut status, error)?;
+                        }
+                        next
+                    }
+                }
+            }
+        };
+    }
+    status
 }
 
 fn job_scenario_cb(
@@ -208,9 +956,43 @@ fn job_scenario_cb(
 ) -> Result<(), AfbError> {
     let param = params.get_ref::<JobScenarioParam>()?;
 
-    // job was kill from API
+    // job was kill from API or by watchdog, in-flight transactions never completed
     if signal != 0 {
-        return Ok(());
+        {
+            let mut state = param.injector.lock_state()?;
+            let mut uid = "none";
+            for transac in state.entries.iter_mut() {
+                if let SimulationStatus::Pending = transac.status {
+                    transac.status = SimulationStatus::Timeout;
+                    uid = transac.uid;
+                }
+            }
+            if param.injector.clock.remaining(state.deadline).is_none() {
+                param.injector.set_timeout(&mut state, uid, param.event)?;
+            }
+            state.deadline = None;
+            // killed while running teardown, do not restart it from the beginning
+            if state.teardown {
+                afb_log_msg!(
+                    Notice,
+                    None,
+                    "scenario:{} interrupted signal:{} during teardown",
+                    param.injector.uid,
+                    signal
+                );
+                return afb_error!(param.injector.uid, "teardown interrupted signal:{}", signal);
+            }
+        }
+        afb_log_msg!(
+            Notice,
+            None,
+            "scenario:{} interrupted signal:{} running teardown",
+            param.injector.uid,
+            signal
+        );
+        let teardown = spawn_teardown_entries(param);
+        param.injector.set_skip_reason("scenario interrupted")?;
+        return teardown;
     }
     job_scenario_exec(param)?;
     Ok(())
@@ -225,9 +1007,294 @@ pub enum SimulationStatus {
     Skip,
     Timeout,
     Retry,
+    Aborted,
     Fail(AfbError),
 }
 
+impl SimulationStatus {
+    pub fn fail(uid: &str, info: String) -> Self {
+        let error: Result<(), AfbError> = afb_error!(uid, "{}", info);
+        SimulationStatus::Fail(error.unwrap_err())
+    }
+}
+
+// comma separated tags as in SCENARIO_TAGS="smoke,dc"
+pub fn tags_from_str(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+// tags config: "smoke,dc" | ["smoke", "dc"]
+pub fn tags_from_jsonc(jtags: &JsoncObj) -> Result<Vec<String>, AfbError> {
+    let tags = match jsonc_to_value(jtags)? {
+        Value::String(value) => tags_from_str(&value),
+        Value::Array(values) => {
+            let mut tags = Vec::new();
+            for value in values {
+                match value {
+                    Value::String(tag) => tags.push(tag),
+                    _ => return afb_error!("injector-tags", "invalid tag:{} in:{}", value, jtags),
+                }
+            }
+            tags
+        }
+        _ => {
+            return afb_error!(
+                "injector-tags",
+                "tags should be a string or an array got:{}",
+                jtags
+            )
+        }
+    };
+    Ok(tags)
+}
+
+// untagged entries always run, empty selection runs everything
+pub fn tags_selected(tags: &[String], selection: &[String]) -> bool {
+    tags.is_empty() || selection.is_empty() || tags.iter().any(|tag| selection.contains(tag))
+}
+
+// on_failure scenario policy, abort stops at first failure, continue runs remaining entries
+#[derive(Clone, Copy)]
+pub enum InjectorFailurePolicy {
+    Abort,
+    Continue,
+}
+
+impl InjectorFailurePolicy {
+    pub fn from_jsonc(jscenario: &JsoncObj) -> Result<Self, AfbError> {
+        let policy = jscenario.default::<String>("on_failure", "abort".to_string())?;
+        match policy.to_lowercase().as_str() {
+            "abort" => Ok(InjectorFailurePolicy::Abort),
+            "continue" => Ok(InjectorFailurePolicy::Continue),
+            other => afb_error!(
+                "injector-failure-policy",
+                "expected on_failure:'abort'|'continue' got:{}",
+                other
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum InjectorBranch {
+    Goto(usize),
+    Block(usize),
+}
+
+impl InjectorBranch {
+    // branch config: "uid" | {"goto": "uid"} | {"block": "name"}
+    pub fn from_jsonc(
+        uid: &str,
+        jbranch: JsoncObj,
+        uids: &[&str],
+        blocks: &[InjectorBlock],
+    ) -> Result<Self, AfbError> {
+        let (goto, name) = match jsonc_to_value(&jbranch)? {
+            Value::String(name) => (true, name),
+            Value::Object(map) => match (map.get("goto"), map.get("block")) {
+                (Some(Value::String(name)), None) => (true, name.clone()),
+                (None, Some(Value::String(name))) => (false, name.clone()),
+                _ => {
+                    return afb_error!(
+                        "injector-branch",
+                        "uid:{} branch should be {{goto:uid}} or {{block:name}} got:{}",
+                        uid,
+                        jbranch
+                    )
+                }
+            },
+            _ => return afb_error!("injector-branch", "uid:{} invalid branch:{}", uid, jbranch),
+        };
+
+        let branch = if goto {
+            uids.iter()
+                .position(|value| *value == name)
+                .map(InjectorBranch::Goto)
+        } else {
+            blocks
+                .iter()
+                .position(|block| block.name == name)
+                .map(InjectorBranch::Block)
+        };
+        match branch {
+            Some(value) => Ok(value),
+            None => afb_error!(
+                "injector-branch",
+                "uid:{} branch target:{} not found",
+                uid,
+                name
+            ),
+        }
+    }
+}
+
+// wait for a target event instead of calling a target verb
+#[derive(Clone)]
+pub struct InjectorWaitEvent {
+    pub api: &'static str,
+    pub event: &'static str,
+    pub expect: Option<JsoncObj>,
+    pub subscribe: Option<&'static str>,
+    pub timeout: time::Duration,
+    pub semaphore: Watchdog,
+}
+
+impl InjectorWaitEvent {
+    // wait_event config: {api, event, expect, timeout, subscribe}
+    pub fn from_jsonc(jsonc: JsoncObj, target: &'static str) -> Result<Self, AfbError> {
+        Ok(Self {
+            api: jsonc.default("api", target)?,
+            event: jsonc.get::<&'static str>("event")?,
+            expect: jsonc.optional::<JsoncObj>("expect")?,
+            subscribe: jsonc.optional::<&'static str>("subscribe")?,
+            timeout: time::Duration::from_millis(jsonc.default("timeout", DEFAULT_CALL_TIMEOUT)?),
+            semaphore: injector_new_watchdog(),
+        })
+    }
+}
+
+// negative expectation, target should reject the request
+#[derive(Clone)]
+pub struct InjectorExpectError {
+    pub status: Option<i32>,
+    pub error: Option<JsoncObj>,
+}
+
+impl InjectorExpectError {
+    // expect_error config: true | {status: -n, ...expected error payload}
+    pub fn from_jsonc(uid: &str, jsonc: JsoncObj) -> Result<Self, AfbError> {
+        let mut map = match jsonc_to_value(&jsonc)? {
+            Value::Bool(true) => {
+                return Ok(Self {
+                    status: None,
+                    error: None,
+                })
+            }
+            Value::Object(map) => map,
+            _ => {
+                return afb_error!(
+                    "injector-expect-error",
+                    "uid:{} expect_error should be true or an object got:{}",
+                    uid,
+                    jsonc
+                )
+            }
+        };
+
+        let status = match map.remove("status") {
+            None => None,
+            Some(value) => match value.as_i64() {
+                Some(status) if status != 0 => Some(status as i32),
+                _ => {
+                    return afb_error!(
+                        "injector-expect-error",
+                        "uid:{} expect_error status should be a non zero integer got:{}",
+                        uid,
+                        value
+                    )
+                }
+            },
+        };
+        let error = if map.is_empty() {
+            None
+        } else {
+            Some(value_to_jsonc(&Value::Object(map))?)
+        };
+        Ok(Self { status, error })
+    }
+}
+
+// concurrent branches, entries[start..end] are dispatched together
+pub struct InjectorParallel {
+    pub uid: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+// scenario level defaults used when parsing transactions
+pub struct InjectorDefaults {
+    pub target: Option<&'static str>,
+    pub prefix: &'static str,
+    pub delay_conf: InjectorDelayConf,
+    pub retry_conf: InjectorRetryConf,
+    pub tolerance: MatchTolerance,
+    pub generator: Generator,
+    pub clock: Clock,
+}
+
+// flatten transactions into entries, parallel branches are kept contiguous
+fn entries_from_jsonc(
+    transactions: &JsoncObj,
+    defaults: &InjectorDefaults,
+    entries: &mut Vec<(InjectorEntry, JsoncObj)>,
+    parallels: &mut Vec<InjectorParallel>,
+) -> Result<(), AfbError> {
+    for idx in 0..transactions.count()? {
+        let transac = transactions.index::<JsoncObj>(idx)?;
+        let jbranches = match transac.optional::<JsoncObj>("parallel")? {
+            None => {
+                entries.push((InjectorEntry::from_jsonc(&transac, defaults)?, transac));
+                continue;
+            }
+            Some(value) => value,
+        };
+
+        let uid = transac.default::<String>("uid", format!("parallel-{}", parallels.len()))?;
+        if jbranches.count()? == 0 {
+            return afb_error!("injector-parallel", "uid:{} parallel block is empty", uid);
+        }
+        let start = entries.len();
+        for jdx in 0..jbranches.count()? {
+            let jbranch = jbranches.index::<JsoncObj>(jdx)?;
+            // branches are single requests sent together, events are not awaited
+            if jbranch.optional::<JsoncObj>("wait_event")?.is_some() {
+                return afb_error!(
+                    "injector-parallel",
+                    "uid:{} wait_event not supported within parallel branches",
+                    uid
+                );
+            }
+            if jbranch.optional::<JsoncObj>("repeat")?.is_some() {
+                return afb_error!(
+                    "injector-parallel",
+                    "uid:{} repeat not supported within parallel branches",
+                    uid
+                );
+            }
+            if let Some(jretry) = jbranch.optional::<JsoncObj>("retry")? {
+                if jretry.default::<u32>("count", 1)? > 1
+                    || jretry.optional::<JsoncObj>("on")?.is_some()
+                {
+                    return afb_error!(
+                        "injector-parallel",
+                        "uid:{} retry count/on not supported within parallel branches",
+                        uid
+                    );
+                }
+            }
+            entries.push((InjectorEntry::from_jsonc(&jbranch, defaults)?, jbranch));
+        }
+        parallels.push(InjectorParallel {
+            uid,
+            start,
+            end: entries.len(),
+        });
+    }
+    Ok(())
+}
+
+// named recovery block, entries[start..end] are only run from a branch
+pub struct InjectorBlock {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone)]
 pub struct InjectorEntry {
     pub uid: &'static str,
     pub target: &'static str,
@@ -237,16 +1304,208 @@ pub struct InjectorEntry {
     pub status: SimulationStatus,
     pub retry: InjectorRetryConf,
     pub delay: time::Duration,
+    pub delay_jitter: f64,
+    pub captures: Vec<InjectorCapture>,
+    pub repeat: Option<InjectorRepeat>,
+    pub wait_event: Option<InjectorWaitEvent>,
+    pub expect_error: Option<InjectorExpectError>,
+    pub tolerance: MatchTolerance,
+    pub expect_latency: Option<time::Duration>,
+    pub latency: Option<time::Duration>,
+    pub depends_on: Vec<usize>,
+    pub tags: Vec<String>,
+    pub skip: Option<String>,
+    pub generator: Generator,
+    pub clock: Clock,
+    pub iterations: u32,
+    pub on_success: Option<InjectorBranch>,
+    pub on_fail: Option<InjectorBranch>,
+    pub branch: Option<String>,
+}
+
+impl InjectorEntry {
+    pub fn from_jsonc(transac: &JsoncObj, defaults: &InjectorDefaults) -> Result<Self, AfbError> {
+        let prefix = defaults.prefix;
+        let delay_conf = &defaults.delay_conf;
+        let uid = transac.get::<&str>("uid")?;
+        let queries = JsoncObj::array();
+
+        let delay = transac.default("delay", DEFAULT_CALL_DELAY)?;
+
+        let retry_conf = match transac.optional::<JsoncObj>("retry")? {
+            None => defaults.retry_conf,
+            Some(jretry) => {
+                InjectorRetryConf::from_jsonc(jretry, &defaults.retry_conf, delay_conf)?
+            }
+        };
+        if let Some(value) = transac.optional::<JsoncObj>("query")? {
+            queries.append(value)?;
+        }
+        let expects = JsoncObj::array();
+        if let Some(value) = transac.optional::<JsoncObj>("expect")? {
+            expects.append(value)?;
+        }
+        // transaction target overrides scenario/binding target
+        let target = match transac.optional::<&'static str>("target")? {
+            Some(value) => value,
+            None => match defaults.target {
+                Some(value) => value,
+                None => return afb_error!("injector-entry", "uid:{} missing target api", uid),
+            },
+        };
+        let verb = match transac.optional::<&'static str>("verb")? {
+            Some(value) => value,
+            None => {
+                let name = format!("{}:{}_req", prefix, uid.replace("-", "_"));
+                to_static_str(name)
+            }
+        };
+        let captures = match transac.optional::<JsoncObj>("capture")? {
+            None => Vec::new(),
+            Some(jcapture) => InjectorCapture::from_jsonc(uid, jcapture)?,
+        };
+        let repeat = match transac.optional::<JsoncObj>("repeat")? {
+            None => None,
+            Some(jrepeat) => Some(InjectorRepeat::from_jsonc(uid, jrepeat)?),
+        };
+        let tolerance = match transac.optional::<JsoncObj>("tolerance")? {
+            None => defaults.tolerance,
+            Some(jtolerance) => MatchTolerance::from_jsonc(jtolerance)?,
+        };
+        // expect_latency: {max: ms} is not affected by delay percentage
+        let expect_latency = match transac.optional::<JsoncObj>("expect_latency")? {
+            None => None,
+            Some(jlatency) => Some(time::Duration::from_millis(jlatency.get::<u64>("max")?)),
+        };
+        let tags = match transac.optional::<JsoncObj>("tags")? {
+            None => Vec::new(),
+            Some(jtags) => tags_from_jsonc(&jtags)?,
+        };
+        let expect_error = match transac.optional::<JsoncObj>("expect_error")? {
+            None => None,
+            Some(jerror) => Some(InjectorExpectError::from_jsonc(uid, jerror)?),
+        };
+        let wait_event = match transac.optional::<JsoncObj>("wait_event")? {
+            None => None,
+            Some(jwait) => Some(InjectorWaitEvent::from_jsonc(jwait, target)?),
+        };
+
+        Ok(InjectorEntry {
+            uid,
+            verb,
+            queries,
+            expects,
+            status: SimulationStatus::Skip,
+            retry: retry_conf,
+            delay: delay_conf.get_duration(delay),
+            delay_jitter: delay_conf.get_jitter(),
+            target,
+            captures,
+            repeat,
+            wait_event,
+            expect_error,
+            tolerance,
+            expect_latency,
+            latency: None,
+            depends_on: Vec::new(),
+            tags,
+            skip: None,
+            generator: defaults.generator.clone(),
+            clock: defaults.clock.clone(),
+            iterations: 0,
+            on_success: None,
+            on_fail: None,
+            branch: None,
+        })
+    }
+
+    // request delay, jitter model draws from scenario generator to follow its seed
+    pub fn get_delay(&self) -> time::Duration {
+        match self.generator.lock() {
+            Ok(mut generator) if self.delay_jitter > 0.0 => self
+                .delay
+                .mul_f64(1.0 + self.delay_jitter * (2.0 * generator.next_f64() - 1.0)),
+            _ => self.delay,
+        }
+    }
+
+    // update status from response and keep named values for later transactions
+    pub fn set_reply(
+        &mut self,
+        reply: InjectorReply,
+        start: time::Instant,
+        captures: &mut CaptureStore,
+    ) -> Option<Value> {
+        self.status = reply.status;
+
+        // round trip time, a slow but valid response fails when over expect_latency
+        self.latency = reply.replied.map(|replied| replied.duration_since(start));
+        if let (SimulationStatus::Done | SimulationStatus::Check, Some(latency), Some(max)) =
+            (&self.status, self.latency, self.expect_latency)
+        {
+            if latency > max {
+                self.status = SimulationStatus::fail(
+                    self.uid,
+                    format!(
+                        "latency:{}ms over expect_latency max:{}ms",
+                        latency.as_millis(),
+                        max.as_millis()
+                    ),
+                );
+            }
+        }
+        if let (SimulationStatus::Done | SimulationStatus::Check, Some(value)) =
+            (&self.status, &reply.received)
+        {
+            if let Err(error) = self
+                .captures
+                .iter()
+                .try_for_each(|capture| capture.extract(value, captures))
+            {
+                self.status = SimulationStatus::Fail(error);
+            }
+        }
+        reply.received
+    }
 }
 
 pub struct ScenarioState {
     pub entries: Vec<InjectorEntry>,
+    pub captures: CaptureStore,
+    pub deadline: Option<time::Instant>,
+    pub verdict: Option<String>,
+    pub teardown: bool,
+}
+
+impl ScenarioState {
+    // skip reason when one depends_on entry did not succeed
+    pub fn unmet_dependency(&self, idx: usize) -> Option<String> {
+        self.entries[idx].depends_on.iter().find_map(|dependency| {
+            let transac = &self.entries[*dependency];
+            let label = match &transac.status {
+                SimulationStatus::Done | SimulationStatus::Check => return None,
+                SimulationStatus::Fail(_) => "failed".to_string(),
+                SimulationStatus::Skip => "skipped".to_string(),
+                other => format!("{:?}", other).to_lowercase(),
+            };
+            Some(format!("depends_on:{} {}", transac.uid, label))
+        })
+    }
 }
 
 pub struct Injector {
     uid: &'static str,
     scenario_job: &'static AfbSchedJob,
     count: usize,
+    blocks: Vec<InjectorBlock>,
+    setup: Option<InjectorBlock>,
+    teardown: Option<InjectorBlock>,
+    on_failure: InjectorFailurePolicy,
+    tags: Vec<String>,
+    timeout: time::Duration,
+    generator: Generator,
+    clock: Clock,
+    parallels: Vec<InjectorParallel>,
     data_set: Mutex<ScenarioState>,
 }
 
@@ -255,72 +1514,209 @@ impl Injector {
         uid: &'static str,
         target: Option<&'static str>,
         prefix: &'static str,
-        scenario_timeout: u64,
-        transactions: JsoncObj,
-        delay_conf: InjectorDelayConf,
-        retry_conf: InjectorRetryConf,
+        jscenario: JsoncObj,
+        config: &BindingConfig,
     ) -> Result<&'static Self, AfbError> {
-        let mut data_set = ScenarioState {
-            entries: Vec::new(),
+        let fragments = &config.fragments;
+        // scenario delay/retry override binding values field by field
+        let mut delay_conf = match jscenario.optional::<JsoncObj>("delay")? {
+            None => config.delay_conf,
+            Some(jdelay) => InjectorDelayConf::from_jsonc(jdelay, &config.delay_conf)?,
+        };
+        if let Some(model) = config.delay_model {
+            delay_conf.model = model;
+        }
+        let mut clock_conf = match jscenario.optional::<JsoncObj>("clock")? {
+            None => config.clock_conf,
+            Some(jclock) => ClockConf::from_jsonc(jclock, &config.clock_conf)?,
+        };
+        if let Some(mode) = config.clock_mode {
+            clock_conf.mode = mode;
+        }
+        let retry_conf = match jscenario.optional::<JsoncObj>("retry")? {
+            None => config.retry_conf,
+            Some(jretry) => InjectorRetryConf::from_jsonc(jretry, &config.retry_conf, &delay_conf)?,
         };
+        let transactions = fragments_expand(
+            &jscenario.get::<JsoncObj>("transactions")?,
+            fragments.clone(),
+        )?;
+        let scenario_timeout = jscenario.default("timeout", transactions.count()? as u64)?;
 
-        let target = match target {
-            Some(value) => value,
-            None => return afb_error!("injector-new", "missing target api from transactions"),
+        // scenario tolerance overrides binding tolerance
+        let tolerance = match jscenario.optional::<JsoncObj>("tolerance")? {
+            None => config.tolerance,
+            Some(jtolerance) => MatchTolerance::from_jsonc(jtolerance)?,
+        };
+        // generators are shared by every scenario transaction, seed makes runs reproducible
+        let generator = ValueGenerator::new(jscenario.optional::<u64>("seed")?);
+        let clock = InjectorClock::new(clock_conf);
+        let defaults = InjectorDefaults {
+            target,
+            prefix,
+            delay_conf,
+            retry_conf,
+            tolerance,
+            generator: generator.clone(),
+            clock: clock.clone(),
         };
 
         // reduce timeout depending on delay percentage ration
-        let mut scenario_timeout = scenario_timeout * delay_conf.percent / 100;
+        let mut scenario_timeout = scenario_timeout * delay_conf.get_percent() / 100;
         if scenario_timeout < DEFAULT_MIN_TIMEOUT {
             scenario_timeout = DEFAULT_MIN_TIMEOUT;
         }
 
-        for idx in 0..transactions.count()? {
-            let transac = transactions.index::<JsoncObj>(idx)?;
-            let uid = transac.get::<&str>("uid")?;
-            let queries = JsoncObj::array();
+        let mut entries = Vec::new();
+        let mut parallels = Vec::new();
+        entries_from_jsonc(&transactions, &defaults, &mut entries, &mut parallels)?;
+        let count = entries.len();
 
-            let delay = transac.default("delay", DEFAULT_CALL_DELAY)?;
+        // recovery blocks are appended after main transactions
+        let mut blocks = Vec::new();
+        if let Some(jblocks) = jscenario.optional::<JsoncObj>("blocks")? {
+            for jblock in jblocks.expand()? {
+                let start = entries.len();
+                let block = fragments_expand(&jblock.obj, fragments.clone())?;
+                entries_from_jsonc(&block, &defaults, &mut entries, &mut parallels)?;
+                blocks.push(InjectorBlock {
+                    name: jblock.key,
+                    start,
+                    end: entries.len(),
+                });
+            }
+        }
 
-            let retry_conf = match transac.optional::<JsoncObj>("retry")? {
-                None => retry_conf,
-                Some(jretry) => InjectorRetryConf::from_jsonc(jretry, &delay_conf)?,
-            };
-            if let Some(value) = transac.optional::<JsoncObj>("query")? {
-                queries.append(value)?;
+        // setup runs before main transactions, teardown always runs last
+        let mut setup = None;
+        let mut teardown = None;
+        for (name, section) in [("setup", &mut setup), ("teardown", &mut teardown)] {
+            if let Some(jsection) = jscenario.optional::<JsoncObj>(name)? {
+                let start = entries.len();
+                let jsection = fragments_expand(&jsection, fragments.clone())?;
+                entries_from_jsonc(&jsection, &defaults, &mut entries, &mut parallels)?;
+                *section = Some(InjectorBlock {
+                    name: name.to_string(),
+                    start,
+                    end: entries.len(),
+                });
+            }
+        }
+
+        // branches may only jump within main transactions or to a named block
+        let uids: Vec<&str> = entries[0..count]
+            .iter()
+            .map(|(transac, _)| transac.uid)
+            .collect();
+        for (idx, (entry, transac)) in entries.iter_mut().enumerate() {
+            let jsuccess = transac.optional::<JsoncObj>("on_success")?;
+            let jfail = transac.optional::<JsoncObj>("on_fail")?;
+            if jsuccess.is_none() && jfail.is_none() {
+                continue;
+            }
+            // blocks, setup, teardown and parallel branches do not support on_success/on_fail
+            if idx >= count
+                || parallels
+                    .iter()
+                    .any(|group| group.start <= idx && idx < group.end)
+            {
+                return afb_error!(
+                    "injector-branch",
+                    "uid:{} on_success/on_fail only allowed on sequential scenario transactions",
+                    entry.uid
+                );
+            }
+            if let Some(jbranch) = jsuccess {
+                entry.on_success = Some(InjectorBranch::from_jsonc(
+                    entry.uid, jbranch, &uids, &blocks,
+                )?);
             }
-            let expects = JsoncObj::array();
-            if let Some(value) = transac.optional::<JsoncObj>("expect")? {
-                expects.append(value)?;
+            if let Some(jbranch) = jfail {
+                entry.on_fail = Some(InjectorBranch::from_jsonc(
+                    entry.uid, jbranch, &uids, &blocks,
+                )?);
             }
-            let verb = match transac.optional::<&'static str>("verb")? {
+        }
+
+        // depends_on refers to the nearest previous entry with the same uid, in run order:
+        // setup, then transactions and blocks, then teardown
+        let uids: Vec<&str> = entries.iter().map(|(transac, _)| transac.uid).collect();
+        let in_block = |block: &Option<InjectorBlock>, idx: usize| match block {
+            Some(block) => block.start <= idx && idx < block.end,
+            None => false,
+        };
+        let mut order: Vec<usize> = (0..entries.len())
+            .filter(|idx| in_block(&setup, *idx))
+            .collect();
+        order.extend(
+            (0..entries.len()).filter(|idx| !in_block(&setup, *idx) && !in_block(&teardown, *idx)),
+        );
+        order.extend((0..entries.len()).filter(|idx| in_block(&teardown, *idx)));
+        for (idx, (entry, transac)) in entries.iter_mut().enumerate() {
+            let jdepends = match transac.optional::<JsoncObj>("depends_on")? {
+                None => continue,
                 Some(value) => value,
-                None => {
-                    let name = format!("{}:{}_req", prefix, uid.replace("-", "_"));
-                    to_static_str(name)
+            };
+            let depends = match jsonc_to_value(&jdepends)? {
+                Value::String(uid) => vec![Value::String(uid)],
+                Value::Array(values) => values,
+                _ => {
+                    return afb_error!(
+                        "injector-depends-on",
+                        "uid:{} depends_on should be a uid or an array of uid",
+                        entry.uid
+                    )
                 }
             };
-
-            data_set.entries.push(InjectorEntry {
-                uid,
-                verb,
-                queries,
-                expects,
-                status: SimulationStatus::Skip,
-                retry: retry_conf,
-                delay: delay_conf.get_duration(delay),
-                target,
-            });
+            for depend in depends {
+                // forward references would always be skipped, refuse them
+                let rank = order.iter().position(|value| *value == idx).unwrap_or(0);
+                let dependency = depend.as_str().and_then(|uid| {
+                    order[0..rank]
+                        .iter()
+                        .rev()
+                        .find(|value| uids[**value] == uid)
+                        .copied()
+                });
+                match dependency {
+                    Some(value) => entry.depends_on.push(value),
+                    None => {
+                        return afb_error!(
+                            "injector-depends-on",
+                            "uid:{} depends_on:{} not found in previous transactions",
+                            entry.uid,
+                            depend
+                        )
+                    }
+                }
+            }
         }
 
+        let data_set = ScenarioState {
+            entries: entries.into_iter().map(|(entry, _)| entry).collect(),
+            captures: CaptureStore::new(),
+            deadline: None,
+            verdict: None,
+            teardown: false,
+        };
+
         let scenario_job = AfbSchedJob::new("iso-15118-Injector")
             .set_callback(job_scenario_cb)
-            .set_exec_watchdog(scenario_timeout as i32);
+            .set_exec_watchdog((scenario_timeout + DEFAULT_WATCHDOG_MARGIN) as i32);
 
         let this = Self {
             uid,
             scenario_job,
-            count: transactions.count()?,
+            count,
+            blocks,
+            setup,
+            teardown,
+            on_failure: InjectorFailurePolicy::from_jsonc(&jscenario)?,
+            tags: config.tags.clone(),
+            timeout: time::Duration::from_secs(scenario_timeout),
+            generator,
+            clock,
+            parallels,
             data_set: Mutex::new(data_set),
         };
 
@@ -331,6 +1727,83 @@ impl Injector {
         self.uid
     }
 
+    pub fn get_parallel(&self, idx: usize) -> Option<&InjectorParallel> {
+        self.parallels
+            .iter()
+            .find(|group| group.start <= idx && idx < group.end)
+    }
+
+    // distinct target apis used by transactions and wait_event
+    pub fn get_targets(&self) -> Result<Vec<&'static str>, AfbError> {
+        let state = self.lock_state()?;
+        let mut targets = Vec::new();
+        for transac in state.entries.iter() {
+            let api = match &transac.wait_event {
+                Some(wait) => wait.api,
+                None => transac.target,
+            };
+            if !targets.contains(&api) {
+                targets.push(api);
+            }
+        }
+        Ok(targets)
+    }
+
+    // abort returns the error, continue keeps the first one as scenario status
+    fn failure_policy(
+        &self,
+        status: &mut Result<(), AfbError>,
+        error: AfbError,
+    ) -> Result<(), AfbError> {
+        if self.lock_state()?.verdict.is_some() || self.clock.is_cancelled() {
+            return Err(error);
+        }
+        match self.on_failure {
+            InjectorFailurePolicy::Abort => Err(error),
+            InjectorFailurePolicy::Continue => {
+                if status.is_ok() {
+                    *status = Err(error);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // scenario deadline reached, keep verdict and notify subscribers once
+    fn set_timeout(
+        &self,
+        state: &mut ScenarioState,
+        uid: &str,
+        event: Option<&AfbEvent>,
+    ) -> Result<(), AfbError> {
+        let verdict = format!(
+            "Timeout after {}s at transaction {}",
+            self.timeout.as_secs(),
+            uid
+        );
+        if state.verdict.is_none() {
+            let jreply = JsoncObj::new();
+            jreply.add("uid", self.uid)?;
+            jreply.add("status", "Timeout")?;
+            jreply.add("timeout", self.timeout.as_secs())?;
+            jreply.add("transaction", uid)?;
+            push_or_print(event, self.uid, &verdict, &jreply);
+            state.verdict = Some(verdict.clone());
+        }
+        afb_error!(self.uid, "{}", verdict)
+    }
+
+    // entries left untouched by the run are reported as skipped with reason
+    fn set_skip_reason(&self, reason: &str) -> Result<(), AfbError> {
+        let mut state = self.lock_state()?;
+        for transac in state.entries.iter_mut() {
+            if let (SimulationStatus::Skip, None) = (&transac.status, &transac.skip) {
+                transac.skip = Some(reason.to_string());
+            }
+        }
+        Ok(())
+    }
+
     #[track_caller]
     pub fn lock_state(&self) -> Result<MutexGuard<'_, ScenarioState>, AfbError> {
         let guard = self.data_set.lock().unwrap();
@@ -341,6 +1814,7 @@ impl Injector {
         &'static self,
         api: AfbApiV4,
         event: &'static AfbEvent,
+        tags: Option<Vec<String>>,
     ) -> Result<i32, AfbError> {
         let job_id = self.scenario_job.post(
             100, // 100ms start delay
@@ -348,45 +1822,160 @@ impl Injector {
                 injector: self,
                 event: Some(event),
                 api,
+                tags,
             },
         )?;
 
         Ok(job_id)
     }
 
+    // interrupt running job at once, in-flight transactions are reported as aborted
     pub fn kill_scenario(&self, job_id: i32) -> Result<JsoncObj, AfbError> {
-        self.scenario_job.abort(job_id)?;
-        self.get_result()
+        self.clock.cancel();
+        {
+            let mut state = self.lock_state()?;
+            let mut uid = None;
+            for transac in state.entries.iter_mut() {
+                if let SimulationStatus::Pending = transac.status {
+                    transac.status = SimulationStatus::Aborted;
+                    uid = Some(transac.uid);
+                }
+            }
+            if let (Some(uid), None) = (uid, &state.verdict) {
+                state.verdict = Some(format!("Stopped at transaction {}", uid));
+            }
+            // teardown keeps no reason, skip_one_entry would never run it
+            let teardown = match &self.teardown {
+                Some(teardown) => teardown.start..teardown.end,
+                None => 0..0,
+            };
+            for (idx, transac) in state.entries.iter_mut().enumerate() {
+                if let (SimulationStatus::Skip, None, false) =
+                    (&transac.status, &transac.skip, teardown.contains(&idx))
+                {
+                    transac.skip = Some("scenario stopped".to_string());
+                }
+            }
+        }
+        // partial result at cancellation time, before teardown runs
+        let result = self.get_result()?;
+        // a running job is not pending anymore, it stops on cancelled clock
+        if let Err(error) = self.scenario_job.abort(job_id) {
+            afb_log_msg!(
+                Debug,
+                None,
+                "scenario:{} job:{} not aborted {}",
+                self.uid,
+                job_id,
+                error
+            );
+        }
+        Ok(result)
+    }
+
+    // tap line numbered from num, idx is the entry index
+    fn get_status(&self, num: usize, idx: usize, transac: &InjectorEntry) -> String {
+        let mut iterations = match transac.repeat {
+            Some(_) => format!(" iterations:{}", transac.iterations),
+            None => String::new(),
+        };
+        if let Some(branch) = &transac.branch {
+            iterations.push_str(format!(" branch:{}", branch).as_str());
+        }
+        if let Some(group) = self.get_parallel(idx) {
+            iterations.push_str(format!(" parallel:{}", group.uid).as_str());
+        }
+        if let Some(latency) = transac.latency {
+            iterations.push_str(format!(" latency:{}ms", latency.as_millis()).as_str());
+        }
+        let verb = format!("{}/{}", transac.target, transac.verb);
+        match &transac.status {
+            SimulationStatus::Done => {
+                format!(
+                    "ok {:04} - {}({})  # Done{}",
+                    num, verb, transac.uid, iterations
+                )
+            }
+            SimulationStatus::Check => {
+                format!(
+                    "ok {:04} - {}({}/{})  # Checked{}",
+                    num, verb, transac.uid, transac.retry.count, iterations
+                )
+            }
+            // failure handled by an on_fail branch does not fail the scenario
+            SimulationStatus::Fail(error) if transac.branch.is_some() => {
+                format!(
+                    "ok {:04} - {}({}/{})  # Branched{} {}",
+                    num, verb, transac.uid, transac.retry.count, iterations, error
+                )
+            }
+            SimulationStatus::Fail(error) => {
+                format!(
+                    "fx {:04} - {}({}/{})  # Fail{} {}",
+                    num, verb, transac.uid, transac.retry.count, iterations, error
+                )
+            }
+            SimulationStatus::Aborted => format!(
+                "fx {:04} - {}({}) # Aborted{}",
+                num, verb, transac.uid, iterations
+            ),
+            SimulationStatus::Skip => format!(
+                "ok {:04} - {}({}) # SKIP {}",
+                num,
+                verb,
+                transac.uid,
+                transac.skip.as_deref().unwrap_or("not executed")
+            ),
+            _ => format!(
+                "fx {:04} - {}({}) # Misc {:?}{}",
+                num, verb, transac.uid, transac.status, iterations
+            ),
+        }
     }
 
     pub fn get_result(&self) -> Result<JsoncObj, AfbError> {
         let state = self.lock_state()?;
         let result = JsoncObj::array();
-        result.append(format!("1..{} # {}", self.count, self.uid).as_str())?;
-        for idx in 0..self.count {
-            let transac = &state.entries[idx];
-            let status = match &transac.status {
-                SimulationStatus::Done => {
-                    format!("ok {:04} - {}({})  # Done", idx, transac.verb, transac.uid)
-                }
-                SimulationStatus::Check => {
-                    format!(
-                        "ok {:04} - {}({}/{})  # Checked",
-                        idx, transac.verb, transac.uid, transac.retry.count
-                    )
-                }
-                SimulationStatus::Fail(error) => {
-                    format!(
-                        "fx {:04} - {}({}/{})  # Fail {}",
-                        idx, transac.verb, transac.uid, error, transac.retry.count
-                    )
-                }
-                _ => format!(
-                    "fx {:04} - {}({}) # Misc {:?}",
-                    idx, transac.verb, transac.uid, transac.status
-                ),
-            };
-            result.append(status.as_str())?;
+
+        // setup runs first, teardown is reported as a subtest after scenario entries
+        let setup = match &self.setup {
+            Some(setup) => setup.start..setup.end,
+            None => 0..0,
+        };
+        let teardown = match &self.teardown {
+            Some(teardown) => teardown.start..teardown.end,
+            None => 0..0,
+        };
+        let mut steps: Vec<usize> = setup.clone().collect();
+        steps.extend(
+            (0..state.entries.len()).filter(|idx| !setup.contains(idx) && !teardown.contains(idx)),
+        );
+
+        let plan = match &self.teardown {
+            Some(_) => steps.len() + 1,
+            None => steps.len(),
+        };
+        result.append(format!("1..{} # {}", plan, self.uid).as_str())?;
+        for (num, idx) in steps.iter().enumerate() {
+            result.append(self.get_status(num, *idx, &state.entries[*idx]).as_str())?;
+        }
+
+        if self.teardown.is_some() {
+            result.append(format!("# Subtest: {}:teardown", self.uid).as_str())?;
+            result.append(format!("    1..{}", teardown.len()).as_str())?;
+            let mut failed = false;
+            for idx in teardown.clone() {
+                let status = self.get_status(idx - teardown.start, idx, &state.entries[idx]);
+                failed |= !status.starts_with("ok");
+                result.append(format!("    {}", status).as_str())?;
+            }
+            let status = if failed { "fx" } else { "ok" };
+            result.append(
+                format!("{} {:04} - {}:teardown", status, steps.len(), self.uid).as_str(),
+            )?;
+        }
+        if let Some(verdict) = &state.verdict {
+            result.append(format!("Bail out! {}", verdict).as_str())?;
         }
         Ok(result)
     }
@@ -408,13 +1997,15 @@ pub struct ResponderReset {
 pub struct Responder {
     nonce: Cell<u32>,
     loop_reset: bool,
+    tolerance: MatchTolerance,
 }
 
 impl Responder {
-    pub fn new(loop_reset: bool) -> &'static Self {
+    pub fn new(loop_reset: bool, tolerance: MatchTolerance) -> &'static Self {
         let this = Responder {
             nonce: Cell::new(0),
             loop_reset,
+            tolerance,
         };
         Box::leak(Box::new(this))
     }
@@ -430,4 +2021,8 @@ impl Responder {
     pub fn get_loop(&self) -> bool {
         self.loop_reset
     }
+
+    pub fn get_tolerance(&self) -> MatchTolerance {
+        self.tolerance
+    }
 }
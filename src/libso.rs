@@ -27,8 +27,24 @@ mod binding;
 #[path = "controller.rs"]
 mod ctrl;
 
+#[path = "template.rs"]
+mod template;
+
+#[path = "matcher.rs"]
+mod matcher;
+
+#[path = "schema.rs"]
+mod schema;
+
+#[path = "clock.rs"]
+mod clock;
+
 pub(crate) mod prelude {
     pub use crate::binding::*;
     pub use crate::verbs::*;
     pub use crate::ctrl::*;
+    pub use crate::template::*;
+    pub use crate::matcher::*;
+    pub use crate::schema::*;
+    pub use crate::clock::*;
 }
\ No newline at end of file
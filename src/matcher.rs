@@ -0,0 +1,340 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Le Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use regex::Regex;
+use serde_json::Value;
+
+// physical value {value, multiplier, unit} tolerance, accept |received-expected| <= max(abs, rel*|expected|)
+#[derive(Clone, Copy)]
+pub struct MatchTolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl MatchTolerance {
+    pub fn default() -> Self {
+        MatchTolerance {
+            absolute: 0.0,
+            relative: 0.0,
+        }
+    }
+
+    pub fn from_jsonc(jsonc: JsoncObj) -> Result<Self, AfbError> {
+        let tolerance = MatchTolerance {
+            absolute: jsonc.default("abs", 0.0)?,
+            relative: jsonc.default("rel", 0.0)?,
+        };
+        if tolerance.absolute < 0.0 || tolerance.relative < 0.0 {
+            return afb_error!(
+                "match-tolerance",
+                "abs/rel should be positive got:{}",
+                jsonc
+            );
+        }
+        Ok(tolerance)
+    }
+
+    fn from_value(path: &str, value: &Value) -> Result<Self, String> {
+        let number = |key: &str| match value.get(key) {
+            None => Ok(0.0),
+            Some(number) => match number.as_f64() {
+                Some(number) if number >= 0.0 => Ok(number),
+                _ => Err(format!(
+                    "path:'{}' invalid $tolerance {}:{}",
+                    path, key, number
+                )),
+            },
+        };
+        Ok(MatchTolerance {
+            absolute: number("abs")?,
+            relative: number("rel")?,
+        })
+    }
+}
+
+// check received value against expect. Plain values keep the historical partial
+// equality, objects with '$' keys are operators:
+//   $eq $ne $gt $gte $lt $lte $regex $in $type $exists $absent $contains $length
+// {value, multiplier, unit} objects are compared as physical values within tolerance
+pub fn expect_match(
+    uid: &str,
+    jreceived: &JsoncObj,
+    jexpected: &JsoncObj,
+    tolerance: &MatchTolerance,
+) -> Result<(), AfbError> {
+    // no operator nor physical value, use jsonc partial comparison
+    let text = jexpected.to_string();
+    if !text.contains("\"$") && !text.contains("\"multiplier\"") {
+        return jreceived.equal(uid, jexpected.clone(), Jequal::Partial);
+    }
+
+    let received = jsonc_to_value(jreceived)?;
+    let expected = jsonc_to_value(jexpected)?;
+    match value_match("", Some(&received), &expected, tolerance) {
+        Ok(()) => Ok(()),
+        Err(error) => afb_error!(uid, "expect mismatch {}", error),
+    }
+}
+
+fn is_operator(expected: &serde_json::Map<String, Value>) -> bool {
+    expected.keys().any(|key| key.starts_with('$'))
+}
+
+// iso15118 physical value: {value, multiplier, unit} with optional $tolerance
+fn is_physical(expected: &serde_json::Map<String, Value>) -> bool {
+    expected.get("value").is_some_and(Value::is_number)
+        && expected.get("multiplier").is_some_and(Value::is_number)
+        && expected
+            .keys()
+            .all(|key| matches!(key.as_str(), "value" | "multiplier" | "unit" | "$tolerance"))
+}
+
+// normalise value*10^multiplier, None when not a physical value
+fn physical_quantity(value: &Value) -> Option<f64> {
+    let number = value.get("value")?.as_f64()?;
+    let multiplier = value.get("multiplier")?.as_i64()?;
+    // divide for negative multiplier to keep exact decimal values
+    match multiplier {
+        0.. => Some(number * 10f64.powi(multiplier as i32)),
+        _ => Some(number / 10f64.powi(-multiplier as i32)),
+    }
+}
+
+fn physical_match(
+    path: &str,
+    received: Option<&Value>,
+    expected: &serde_json::Map<String, Value>,
+    tolerance: &MatchTolerance,
+) -> Result<(), String> {
+    let tolerance = match expected.get("$tolerance") {
+        None => *tolerance,
+        Some(value) => MatchTolerance::from_value(path, value)?,
+    };
+    let (received, quantity) =
+        match received.and_then(|value| Some((value, physical_quantity(value)?))) {
+            Some(value) => value,
+            None => {
+                return Err(format!(
+                    "path:'{}' expected physical value got:{:?}",
+                    path, received
+                ))
+            }
+        };
+    let expected_quantity = match physical_quantity(&Value::Object(expected.clone())) {
+        Some(value) => value,
+        None => return Err(format!("path:'{}' invalid physical value multiplier", path)),
+    };
+
+    if let (Some(unit), Some(expected_unit)) = (received.get("unit"), expected.get("unit")) {
+        if unit != expected_unit {
+            return Err(format!(
+                "path:'{}' expected unit:{} got:{}",
+                path, expected_unit, unit
+            ));
+        }
+    }
+
+    let delta = (quantity - expected_quantity).abs();
+    let allowed = tolerance
+        .absolute
+        .max(tolerance.relative * expected_quantity.abs());
+    // absorb float rounding from multiplier normalisation
+    if delta > allowed + f64::EPSILON * expected_quantity.abs().max(1.0) {
+        return Err(format!(
+            "path:'{}' expected:{} got:{} tolerance:{}",
+            path, expected_quantity, quantity, allowed
+        ));
+    }
+    Ok(())
+}
+
+fn value_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn value_number(path: &str, value: Option<&Value>) -> Result<f64, String> {
+    match value.and_then(Value::as_f64) {
+        Some(number) => Ok(number),
+        None => Err(format!("path:'{}' expected number got:{:?}", path, value)),
+    }
+}
+
+// path is a json pointer used to locate mismatch within error message
+fn value_match(
+    path: &str,
+    received: Option<&Value>,
+    expected: &Value,
+    tolerance: &MatchTolerance,
+) -> Result<(), String> {
+    match expected {
+        Value::Object(map) if is_physical(map) => physical_match(path, received, map, tolerance),
+        Value::Object(map) if is_operator(map) => {
+            for (operator, argument) in map {
+                operator_match(path, received, operator, argument, tolerance)?;
+            }
+            Ok(())
+        }
+        Value::Object(map) => {
+            let received = match received {
+                Some(Value::Object(value)) => value,
+                _ => {
+                    return Err(format!(
+                        "path:'{}' expected object got:{:?}",
+                        path, received
+                    ))
+                }
+            };
+            for (key, expected) in map {
+                value_match(
+                    &format!("{}/{}", path, key),
+                    received.get(key),
+                    expected,
+                    tolerance,
+                )?;
+            }
+            Ok(())
+        }
+        Value::Array(array) => {
+            let received = match received {
+                Some(Value::Array(value)) if value.len() >= array.len() => value,
+                _ => return Err(format!("path:'{}' expected array got:{:?}", path, received)),
+            };
+            for (idx, expected) in array.iter().enumerate() {
+                value_match(
+                    &format!("{}/{}", path, idx),
+                    received.get(idx),
+                    expected,
+                    tolerance,
+                )?;
+            }
+            Ok(())
+        }
+        Value::Number(number) => match received.and_then(Value::as_f64) {
+            Some(value) if Some(value) == number.as_f64() => Ok(()),
+            _ => Err(format!(
+                "path:'{}' expected:{} got:{:?}",
+                path, number, received
+            )),
+        },
+        _ => match received {
+            Some(value) if value == expected => Ok(()),
+            _ => Err(format!(
+                "path:'{}' expected:{} got:{:?}",
+                path, expected, received
+            )),
+        },
+    }
+}
+
+fn operator_match(
+    path: &str,
+    received: Option<&Value>,
+    operator: &str,
+    argument: &Value,
+    tolerance: &MatchTolerance,
+) -> Result<(), String> {
+    match operator {
+        "$exists" | "$absent" => {
+            let exists = match argument.as_bool() {
+                Some(value) => value == (operator == "$exists"),
+                None => return Err(format!("path:'{}' {} expect a boolean", path, operator)),
+            };
+            if exists != received.is_some() {
+                return Err(format!(
+                    "path:'{}' {}:{} got:{:?}",
+                    path, operator, argument, received
+                ));
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // every other operator needs a received value
+    let value = match received {
+        Some(value) => value,
+        None => return Err(format!("path:'{}' {} missing value", path, operator)),
+    };
+
+    let matching = match operator {
+        "$eq" => value_match(path, received, argument, tolerance).is_ok(),
+        "$ne" => value_match(path, received, argument, tolerance).is_err(),
+        "$gt" => value_number(path, received)? > value_number(path, Some(argument))?,
+        "$gte" => value_number(path, received)? >= value_number(path, Some(argument))?,
+        "$lt" => value_number(path, received)? < value_number(path, Some(argument))?,
+        "$lte" => value_number(path, received)? <= value_number(path, Some(argument))?,
+        "$regex" => {
+            let pattern = match argument.as_str() {
+                Some(value) => value,
+                None => return Err(format!("path:'{}' $regex expect a string", path)),
+            };
+            let regex = match Regex::new(pattern) {
+                Ok(value) => value,
+                Err(error) => return Err(format!("path:'{}' invalid $regex {}", path, error)),
+            };
+            match value.as_str() {
+                Some(text) => regex.is_match(text),
+                None => false,
+            }
+        }
+        "$in" => match argument.as_array() {
+            Some(values) => values
+                .iter()
+                .any(|expected| value_match(path, received, expected, tolerance).is_ok()),
+            None => return Err(format!("path:'{}' $in expect an array", path)),
+        },
+        "$type" => match argument.as_str() {
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some(name) => value_type(value) == name,
+            None => return Err(format!("path:'{}' $type expect a string", path)),
+        },
+        "$contains" => match value.as_array() {
+            Some(values) => values
+                .iter()
+                .any(|element| value_match(path, Some(element), argument, tolerance).is_ok()),
+            None => false,
+        },
+        "$length" => {
+            let length = match value {
+                Value::Array(values) => values.len(),
+                Value::String(text) => text.chars().count(),
+                Value::Object(map) => map.len(),
+                _ => return Err(format!("path:'{}' $length on {}", path, value_type(value))),
+            };
+            let length = Value::from(length);
+            return value_match(
+                &format!("{}.length", path),
+                Some(&length),
+                argument,
+                tolerance,
+            );
+        }
+        _ => return Err(format!("path:'{}' unknown operator:{}", path, operator)),
+    };
+
+    if !matching {
+        return Err(format!(
+            "path:'{}' {}:{} got:{}",
+            path, operator, argument, value
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,309 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Le Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use serde_json::Value;
+
+// warn only reports invalid fields, strict refuses to load the config
+#[derive(Clone, Copy, PartialEq)]
+pub enum SchemaMode {
+    Warn,
+    Strict,
+}
+
+impl SchemaMode {
+    pub fn parse(mode: &str) -> Result<Self, AfbError> {
+        match mode.to_lowercase().as_str() {
+            "warn" => Ok(SchemaMode::Warn),
+            "strict" => Ok(SchemaMode::Strict),
+            other => afb_error!(
+                "simu-binding-schema",
+                "expected schema:'warn'|'strict' got:{}",
+                other
+            ),
+        }
+    }
+}
+
+pub enum SchemaNode {
+    Any,
+    Bool,
+    Integer,
+    Number,
+    String,
+    Array(&'static SchemaNode),
+    // free keys, every value uses the same schema
+    Map(&'static SchemaNode),
+    Object(&'static [(&'static str, &'static SchemaNode)]),
+    OneOf(&'static [&'static SchemaNode]),
+}
+
+static TOLERANCE: SchemaNode =
+    SchemaNode::Object(&[("abs", &SchemaNode::Number), ("rel", &SchemaNode::Number)]);
+
+static DELAY: SchemaNode = SchemaNode::Object(&[
+    ("model", &SchemaNode::String),
+    ("percent", &SchemaNode::Integer),
+    ("min", &SchemaNode::Integer),
+    ("max", &SchemaNode::Integer),
+    ("value", &SchemaNode::Integer),
+    ("jitter", &SchemaNode::Number),
+]);
+
+static CLOCK: SchemaNode = SchemaNode::Object(&[
+    ("mode", &SchemaNode::String),
+    ("factor", &SchemaNode::Number),
+    ("field", &SchemaNode::String),
+]);
+
+static RETRY: SchemaNode = SchemaNode::Object(&[
+    ("delay", &SchemaNode::Integer),
+    ("timeout", &SchemaNode::Integer),
+    ("count", &SchemaNode::Integer),
+    ("on", &TAGS),
+    ("backoff", &SchemaNode::String),
+    ("factor", &SchemaNode::Number),
+    ("max_delay", &SchemaNode::Integer),
+    ("jitter", &SchemaNode::Number),
+    ("budget", &SchemaNode::Integer),
+]);
+
+// "smoke,dc" | ["smoke", "dc"]
+static TAGS: SchemaNode =
+    SchemaNode::OneOf(&[&SchemaNode::String, &SchemaNode::Array(&SchemaNode::String)]);
+
+// "uid" | ["uid-a", "uid-b"]
+static UIDS: SchemaNode =
+    SchemaNode::OneOf(&[&SchemaNode::String, &SchemaNode::Array(&SchemaNode::String)]);
+
+static BRANCH: SchemaNode = SchemaNode::OneOf(&[
+    &SchemaNode::String,
+    &SchemaNode::Object(&[
+        ("goto", &SchemaNode::String),
+        ("block", &SchemaNode::String),
+    ]),
+]);
+
+static TRANSACTION: SchemaNode = SchemaNode::Object(&[
+    ("uid", &SchemaNode::String),
+    ("verb", &SchemaNode::String),
+    ("target", &SchemaNode::String),
+    ("delay", &SchemaNode::Integer),
+    ("query", &SchemaNode::Any),
+    ("response", &SchemaNode::Any),
+    ("expect", &SchemaNode::Any),
+    ("retry", &RETRY),
+    ("capture", &SchemaNode::Map(&SchemaNode::String)),
+    (
+        "repeat",
+        &SchemaNode::Object(&[
+            ("count", &SchemaNode::Integer),
+            ("until", &SchemaNode::Any),
+            ("max", &SchemaNode::Integer),
+        ]),
+    ),
+    ("on_success", &BRANCH),
+    ("on_fail", &BRANCH),
+    ("parallel", &SchemaNode::Array(&TRANSACTION)),
+    (
+        "expect_error",
+        &SchemaNode::OneOf(&[&SchemaNode::Bool, &SchemaNode::Map(&SchemaNode::Any)]),
+    ),
+    (
+        "expect_latency",
+        &SchemaNode::Object(&[("max", &SchemaNode::Integer)]),
+    ),
+    ("depends_on", &UIDS),
+    ("tags", &TAGS),
+    (
+        "wait_event",
+        &SchemaNode::Object(&[
+            ("api", &SchemaNode::String),
+            ("event", &SchemaNode::String),
+            ("expect", &SchemaNode::Any),
+            ("subscribe", &SchemaNode::String),
+            ("timeout", &SchemaNode::Integer),
+        ]),
+    ),
+    ("tolerance", &TOLERANCE),
+    ("injector_only", &SchemaNode::Bool),
+    ("include", &SchemaNode::String),
+    ("overrides", &SchemaNode::Map(&SchemaNode::Any)),
+]);
+
+static TRANSACTIONS: SchemaNode = SchemaNode::Array(&TRANSACTION);
+
+static SCENARIO: SchemaNode = SchemaNode::Object(&[
+    ("uid", &SchemaNode::String),
+    ("name", &SchemaNode::String),
+    ("info", &SchemaNode::String),
+    ("prefix", &SchemaNode::String),
+    ("target", &SchemaNode::String),
+    ("timeout", &SchemaNode::Integer),
+    ("on_failure", &SchemaNode::String),
+    ("tags", &TAGS),
+    ("seed", &SchemaNode::Integer),
+    ("tolerance", &TOLERANCE),
+    ("delay", &DELAY),
+    ("retry", &RETRY),
+    ("clock", &CLOCK),
+    ("vars", &SchemaNode::Map(&SchemaNode::Any)),
+    (
+        "parameters",
+        &SchemaNode::Array(&SchemaNode::Map(&SchemaNode::Any)),
+    ),
+    ("blocks", &SchemaNode::Map(&TRANSACTIONS)),
+    ("setup", &TRANSACTIONS),
+    ("teardown", &TRANSACTIONS),
+    ("transactions", &TRANSACTIONS),
+]);
+
+static BINDING: SchemaNode = SchemaNode::Object(&[
+    ("uid", &SchemaNode::String),
+    ("api", &SchemaNode::String),
+    ("info", &SchemaNode::String),
+    ("path", &SchemaNode::String),
+    ("simulation", &SchemaNode::String),
+    ("schema", &SchemaNode::String),
+    ("loop", &SchemaNode::Bool),
+    ("target", &SchemaNode::String),
+    ("autorun", &SchemaNode::Integer),
+    ("permission", &SchemaNode::String),
+    ("verbosity", &SchemaNode::Integer),
+    ("delay", &DELAY),
+    ("retry", &RETRY),
+    ("clock", &CLOCK),
+    ("tolerance", &TOLERANCE),
+    ("fragments", &SchemaNode::Map(&TRANSACTIONS)),
+    ("scenarios", &SchemaNode::Array(&SCENARIO)),
+]);
+
+impl SchemaNode {
+    fn name(&self) -> &'static str {
+        match self {
+            SchemaNode::Any => "any",
+            SchemaNode::Bool => "boolean",
+            SchemaNode::Integer => "integer",
+            SchemaNode::Number => "number",
+            SchemaNode::String => "string",
+            SchemaNode::Array(_) => "array",
+            SchemaNode::Map(_) | SchemaNode::Object(_) => "object",
+            SchemaNode::OneOf(_) => "oneof",
+        }
+    }
+
+    // collect one 'path: issue' per unknown or mistyped field
+    fn check(&self, path: &str, value: &Value, issues: &mut Vec<String>) {
+        // ${var} placeholders are resolved later, type is checked at parse time
+        if let Value::String(text) = value {
+            if text.contains("${") {
+                return;
+            }
+        }
+
+        match (self, value) {
+            (SchemaNode::Any, _) => {}
+            (SchemaNode::Bool, Value::Bool(_)) => {}
+            (SchemaNode::Integer, Value::Number(number)) if number.is_i64() || number.is_u64() => {}
+            (SchemaNode::Number, Value::Number(_)) => {}
+            (SchemaNode::String, Value::String(_)) => {}
+            (SchemaNode::Array(node), Value::Array(values)) => {
+                for (idx, value) in values.iter().enumerate() {
+                    node.check(&format!("{}[{}]", path, idx), value, issues);
+                }
+            }
+            (SchemaNode::Map(node), Value::Object(map)) => {
+                for (key, value) in map {
+                    node.check(&schema_path(path, key), value, issues);
+                }
+            }
+            (SchemaNode::Object(fields), Value::Object(map)) => {
+                for (key, value) in map {
+                    match fields.iter().find(|(name, _)| name == key) {
+                        Some((_, node)) => node.check(&schema_path(path, key), value, issues),
+                        None => issues.push(format!("{}: unknown field", schema_path(path, key))),
+                    }
+                }
+            }
+            (SchemaNode::OneOf(nodes), _) => {
+                let mut candidates = Vec::new();
+                for node in nodes.iter() {
+                    let mut errors = Vec::new();
+                    node.check(path, value, &mut errors);
+                    if errors.is_empty() {
+                        return;
+                    }
+                    candidates.push(node.name());
+                }
+                // report nested issues of the only matching type
+                let same_type: Vec<&&SchemaNode> = nodes
+                    .iter()
+                    .filter(|node| node.name() == schema_type(value))
+                    .collect();
+                match same_type.as_slice() {
+                    [node] => node.check(path, value, issues),
+                    _ => issues.push(format!(
+                        "{}: expected {} got {}",
+                        path,
+                        candidates.join("|"),
+                        schema_type(value)
+                    )),
+                }
+            }
+            _ => issues.push(format!(
+                "{}: expected {} got {}",
+                path,
+                self.name(),
+                schema_type(value)
+            )),
+        }
+    }
+}
+
+fn schema_path(path: &str, key: &str) -> String {
+    match path {
+        "" => key.to_string(),
+        _ => format!("{}.{}", path, key),
+    }
+}
+
+fn schema_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// check binding config against injector schema, report paths as 'scenarios[0].transactions[3].retry.cuont'
+pub fn schema_validate(jconf: &JsoncObj, mode: SchemaMode) -> Result<(), AfbError> {
+    let mut issues = Vec::new();
+    BINDING.check("", &jsonc_to_value(jconf)?, &mut issues);
+
+    for issue in &issues {
+        afb_log_msg!(Warning, None, "config schema {}", issue);
+    }
+    if mode == SchemaMode::Strict && !issues.is_empty() {
+        return afb_error!(
+            "simu-binding-schema",
+            "invalid config {} issue(s): {}",
+            issues.len(),
+            issues.join(", ")
+        );
+    }
+    Ok(())
+}
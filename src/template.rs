@@ -0,0 +1,506 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Le Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use afbv4::prelude::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// captured values indexed by 'transaction-uid.capture-name'
+pub type CaptureStore = HashMap<String, Value>;
+
+pub fn jsonc_to_value(jsonc: &JsoncObj) -> Result<Value, AfbError> {
+    match serde_json::from_str(jsonc.to_string().as_str()) {
+        Ok(value) => Ok(value),
+        Err(error) => afb_error!("jsonc-to-value", "invalid json:{} error:{}", jsonc, error),
+    }
+}
+
+pub fn value_to_jsonc(value: &Value) -> Result<JsoncObj, AfbError> {
+    JsoncObj::parse(value.to_string().as_str())
+}
+
+// replace every ${name} placeholder found in value strings. When a string only holds
+// one placeholder the resolved value replaces it with its own json type, otherwise
+// the resolved value is interpolated as text. In non strict mode unknown placeholders
+// are kept untouched for a later expansion pass.
+pub fn placeholder_expand(
+    value: &Value,
+    lookup: &mut dyn FnMut(&str) -> Result<Option<Value>, AfbError>,
+    strict: bool,
+) -> Result<Value, AfbError> {
+    let expanded = match value {
+        Value::String(text) => string_expand(text, lookup, strict)?,
+        Value::Array(values) => {
+            let mut array = Vec::with_capacity(values.len());
+            for value in values {
+                array.push(placeholder_expand(value, lookup, strict)?);
+            }
+            Value::Array(array)
+        }
+        Value::Object(map) => {
+            let mut object = serde_json::Map::with_capacity(map.len());
+            for (key, value) in map {
+                object.insert(key.clone(), placeholder_expand(value, lookup, strict)?);
+            }
+            Value::Object(object)
+        }
+        _ => value.clone(),
+    };
+    Ok(expanded)
+}
+
+fn string_expand(
+    text: &str,
+    lookup: &mut dyn FnMut(&str) -> Result<Option<Value>, AfbError>,
+    strict: bool,
+) -> Result<Value, AfbError> {
+    // single placeholder string keeps resolved value type
+    if text.starts_with("${") && text.ends_with('}') && text.find('}') == Some(text.len() - 1) {
+        let name = &text[2..text.len() - 1];
+        return match lookup(name)? {
+            Some(value) => Ok(value),
+            None if strict => afb_error!("placeholder-expand", "unresolved placeholder:{}", text),
+            None => Ok(Value::String(text.to_string())),
+        };
+    }
+
+    let mut expanded = String::new();
+    let mut remaining = text;
+    while let Some(start) = remaining.find("${") {
+        let end = match remaining[start..].find('}') {
+            Some(value) => start + value,
+            None => break,
+        };
+        expanded.push_str(&remaining[..start]);
+        let name = &remaining[start + 2..end];
+        match lookup(name)? {
+            Some(Value::String(value)) => expanded.push_str(&value),
+            Some(value) => expanded.push_str(&value.to_string()),
+            None if strict => {
+                return afb_error!(
+                    "placeholder-expand",
+                    "unresolved placeholder:{} in:{}",
+                    name,
+                    text
+                )
+            }
+            None => expanded.push_str(&remaining[start..=end]),
+        }
+        remaining = &remaining[end + 1..];
+    }
+    expanded.push_str(remaining);
+    Ok(Value::String(expanded))
+}
+
+// named capture of a reply value using a json pointer (RFC-6901)
+#[derive(Clone)]
+pub struct InjectorCapture {
+    pub name: String,
+    pub pointer: String,
+}
+
+impl InjectorCapture {
+    // capture config: {"name": "/json/pointer", ...} stored as 'uid.name'
+    pub fn from_jsonc(uid: &str, jcapture: JsoncObj) -> Result<Vec<Self>, AfbError> {
+        let mut captures = Vec::new();
+        let map = match jsonc_to_value(&jcapture)? {
+            Value::Object(map) => map,
+            _ => {
+                return afb_error!(
+                    "injector-capture",
+                    "uid:{} capture should be an object of name:json-pointer",
+                    uid
+                )
+            }
+        };
+        for (name, pointer) in map {
+            let pointer = match pointer {
+                Value::String(value) if value.is_empty() || value.starts_with('/') => value,
+                _ => {
+                    return afb_error!(
+                        "injector-capture",
+                        "uid:{} capture:{} invalid json pointer:{}",
+                        uid,
+                        name,
+                        pointer
+                    )
+                }
+            };
+            captures.push(InjectorCapture {
+                name: format!("{}.{}", uid, name),
+                pointer,
+            });
+        }
+        Ok(captures)
+    }
+
+    pub fn extract(&self, received: &Value, store: &mut CaptureStore) -> Result<(), AfbError> {
+        match received.pointer(self.pointer.as_str()) {
+            Some(value) => {
+                store.insert(self.name.clone(), value.clone());
+                Ok(())
+            }
+            None => afb_error!(
+                "injector-capture",
+                "capture:{} pointer:{} not found in response",
+                self.name,
+                self.pointer
+            ),
+        }
+    }
+}
+
+// send time value generators: ${now} ${now:ms} ${uuid} ${mac} ${counter:name} ${random:a..b}
+// with a seed, random values and counters restart identically on every scenario run
+pub struct ValueGenerator {
+    seed: Option<u64>,
+    state: u64,
+    counters: HashMap<String, i64>,
+}
+
+pub type Generator = Arc<Mutex<ValueGenerator>>;
+
+impl ValueGenerator {
+    pub fn new(seed: Option<u64>) -> Generator {
+        let mut this = ValueGenerator {
+            seed,
+            state: 0,
+            counters: HashMap::new(),
+        };
+        this.reset();
+        Arc::new(Mutex::new(this))
+    }
+
+    // called at scenario start
+    pub fn reset(&mut self) {
+        self.counters.clear();
+        self.state = match self.seed {
+            Some(value) => value,
+            None => match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(value) => value.as_nanos() as u64,
+                Err(_) => 0,
+            },
+        };
+    }
+
+    // splitmix64, good enough for test values and reproducible from seed
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut value = self.state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+        value ^ (value >> 31)
+    }
+
+    // uniform float within [0,1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn random(&mut self, range: &str) -> Result<Value, AfbError> {
+        let (min, max) = match range.split_once("..") {
+            Some(value) => value,
+            None => return afb_error!("value-generator", "random expect min..max got:{}", range),
+        };
+        if let (Ok(min), Ok(max)) = (min.trim().parse::<i64>(), max.trim().parse::<i64>()) {
+            if min > max {
+                return afb_error!("value-generator", "random invalid range:{}", range);
+            }
+            // i128 span holds the full i64 range without overflow
+            let span = (max as i128 - min as i128 + 1) as u128;
+            let offset = (self.next_u64() as u128 % span) as i128;
+            return Ok(Value::from((min as i128 + offset) as i64));
+        }
+        match (min.trim().parse::<f64>(), max.trim().parse::<f64>()) {
+            (Ok(min), Ok(max)) if min <= max => {
+                Ok(Value::from(min + self.next_f64() * (max - min)))
+            }
+            _ => afb_error!("value-generator", "random invalid range:{}", range),
+        }
+    }
+
+    // None when name is not a generator expression
+    pub fn generate(&mut self, name: &str) -> Result<Option<Value>, AfbError> {
+        let (kind, argument) = match name.split_once(':') {
+            Some((kind, argument)) => (kind, Some(argument)),
+            None => (name, None),
+        };
+        let value = match (kind, argument) {
+            ("now", None) | ("now", Some("s")) | ("now", Some("ms")) => {
+                let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                    Ok(value) => value,
+                    Err(_) => return afb_error!("value-generator", "invalid system time"),
+                };
+                match argument {
+                    Some("ms") => Value::from(now.as_millis() as u64),
+                    _ => Value::from(now.as_secs()),
+                }
+            }
+            ("uuid", None) => {
+                let high = self.next_u64();
+                let low = self.next_u64();
+                // version 4, variant 10xx
+                let high = (high & 0xffff_ffff_ffff_0fff) | 0x0000_0000_0000_4000;
+                let low = (low & 0x3fff_ffff_ffff_ffff) | 0x8000_0000_0000_0000;
+                Value::from(format!(
+                    "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+                    high >> 32,
+                    (high >> 16) & 0xffff,
+                    high & 0xffff,
+                    low >> 48,
+                    low & 0xffff_ffff_ffff
+                ))
+            }
+            ("mac", None) => {
+                // locally administered unicast address
+                let bytes = self.next_u64().to_be_bytes();
+                let first = (bytes[0] & 0xfc) | 0x02;
+                Value::from(format!(
+                    "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                    first, bytes[1], bytes[2], bytes[3], bytes[4], bytes[5]
+                ))
+            }
+            ("counter", Some(counter)) => {
+                let count = self.counters.entry(counter.to_string()).or_insert(0);
+                *count += 1;
+                Value::from(*count)
+            }
+            ("random", Some(range)) => self.random(range)?,
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+}
+
+// expand ${uid.name} placeholders from previously captured values, then generators
+pub fn captures_expand(
+    jquery: &JsoncObj,
+    store: &CaptureStore,
+    generator: &Generator,
+) -> Result<JsoncObj, AfbError> {
+    if !jquery.to_string().contains("${") {
+        return Ok(jquery.clone());
+    }
+    let mut generator = match generator.lock() {
+        Ok(value) => value,
+        Err(_) => return afb_error!("captures-expand", "(hoops) fail to lock value generator"),
+    };
+    let query = jsonc_to_value(jquery)?;
+    let expanded = placeholder_expand(
+        &query,
+        &mut |name| match store.get(name) {
+            Some(value) => Ok(Some(value.clone())),
+            None => generator.generate(name),
+        },
+        true,
+    )?;
+    value_to_jsonc(&expanded)
+}
+
+const FRAGMENT_MAX_DEPTH: usize = 8; // nested include max depth
+
+// recursive merge of patch object into target value
+fn value_merge(target: &mut Value, patch: &Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                value_merge(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+fn fragments_include(
+    transactions: &Value,
+    fragments: &Value,
+    depth: usize,
+    expanded: &mut Vec<Value>,
+) -> Result<(), AfbError> {
+    let transactions = match transactions.as_array() {
+        Some(value) => value,
+        None => {
+            return afb_error!(
+                "fragments-expand",
+                "transactions should be a valid array got:{}",
+                transactions
+            )
+        }
+    };
+
+    for transac in transactions {
+        let include = match transac.get("include") {
+            None => {
+                expanded.push(transac.clone());
+                continue;
+            }
+            Some(Value::String(value)) => value,
+            Some(value) => return afb_error!("fragments-expand", "invalid include:{}", value),
+        };
+
+        if depth >= FRAGMENT_MAX_DEPTH {
+            return afb_error!(
+                "fragments-expand",
+                "include:{} nested too deep (max:{})",
+                include,
+                FRAGMENT_MAX_DEPTH
+            );
+        }
+        let fragment = match fragments.get(include) {
+            Some(value) => value,
+            None => {
+                return afb_error!("fragments-expand", "include:{} fragment not found", include)
+            }
+        };
+
+        let mut included = Vec::new();
+        fragments_include(fragment, fragments, depth + 1, &mut included)?;
+
+        // per include query overrides indexed by fragment transaction uid
+        if let Some(overrides) = transac.get("overrides") {
+            for entry in included.iter_mut() {
+                let patch = match entry.get("uid").and_then(Value::as_str) {
+                    Some(uid) => overrides.get(uid),
+                    None => None,
+                };
+                if let Some(patch) = patch.cloned() {
+                    value_merge(&mut entry["query"], &patch);
+                }
+            }
+        }
+        expanded.extend(included);
+    }
+    Ok(())
+}
+
+// replace {"include": "fragment-uid"} transactions with fragment transactions
+pub fn fragments_expand(
+    jtransactions: &JsoncObj,
+    jfragments: Option<JsoncObj>,
+) -> Result<JsoncObj, AfbError> {
+    let fragments = match jfragments {
+        Some(value) => jsonc_to_value(&value)?,
+        None => Value::Object(serde_json::Map::new()),
+    };
+    let transactions = jsonc_to_value(jtransactions)?;
+
+    let mut expanded = Vec::new();
+    fragments_include(&transactions, &fragments, 0, &mut expanded)?;
+    value_to_jsonc(&Value::Array(expanded))
+}
+
+// include fragments within scenario transactions, setup, teardown and blocks
+fn scenario_include(scenario: &mut Value, fragments: &Value) -> Result<(), AfbError> {
+    let map = match scenario {
+        Value::Object(map) => map,
+        _ => return Ok(()),
+    };
+    for name in ["transactions", "setup", "teardown"] {
+        if let Some(transactions) = map.get_mut(name) {
+            let mut expanded = Vec::new();
+            fragments_include(transactions, fragments, 0, &mut expanded)?;
+            *transactions = Value::Array(expanded);
+        }
+    }
+    if let Some(Value::Object(blocks)) = map.get_mut("blocks") {
+        for transactions in blocks.values_mut() {
+            let mut expanded = Vec::new();
+            fragments_include(transactions, fragments, 0, &mut expanded)?;
+            *transactions = Value::Array(expanded);
+        }
+    }
+    Ok(())
+}
+
+// expand ${param} placeholders from one parameter set. Fragments are included first
+// to expand their placeholders, unknown ones are kept for runtime captures.
+pub fn parameters_expand(
+    jscenario: &JsoncObj,
+    jparameters: &JsoncObj,
+    jfragments: Option<JsoncObj>,
+) -> Result<JsoncObj, AfbError> {
+    let parameters = jsonc_to_value(jparameters)?;
+    let fragments = match jfragments {
+        Some(value) => jsonc_to_value(&value)?,
+        None => Value::Object(serde_json::Map::new()),
+    };
+
+    let mut scenario = jsonc_to_value(jscenario)?;
+    if let Value::Object(map) = &mut scenario {
+        map.remove("parameters");
+    }
+    scenario_include(&mut scenario, &fragments)?;
+
+    let expanded = placeholder_expand(
+        &scenario,
+        &mut |name| Ok(parameters.get(name).cloned()),
+        false,
+    )?;
+    value_to_jsonc(&expanded)
+}
+
+// environment value keeps number/boolean type when it holds one
+fn env_value(name: &str) -> Result<Value, AfbError> {
+    let text = match std::env::var(name) {
+        Ok(value) => value,
+        Err(_) => return afb_error!("vars-expand", "undefined environment variable:{}", name),
+    };
+    match serde_json::from_str::<Value>(&text) {
+        Ok(value) if value.is_number() || value.is_boolean() => Ok(value),
+        _ => Ok(Value::String(text)),
+    }
+}
+
+// expand scenario ${var} and ${env:NAME} placeholders at parse time. Fragments are
+// included first, unknown placeholders are kept for parameters, captures and generators.
+pub fn variables_expand(
+    jscenario: &JsoncObj,
+    jfragments: Option<JsoncObj>,
+) -> Result<JsoncObj, AfbError> {
+    let fragments = match jfragments {
+        Some(value) => jsonc_to_value(&value)?,
+        None => Value::Object(serde_json::Map::new()),
+    };
+    let mut scenario = jsonc_to_value(jscenario)?;
+    scenario_include(&mut scenario, &fragments)?;
+
+    // vars may themselves reference environment
+    let mut env_lookup = |name: &str| match name.strip_prefix("env:") {
+        Some(name) => Ok(Some(env_value(name)?)),
+        None => Ok(None),
+    };
+    let vars = match scenario.get("vars") {
+        None => serde_json::Map::new(),
+        Some(value) => match placeholder_expand(value, &mut env_lookup, false)? {
+            Value::Object(map) => map,
+            _ => {
+                return afb_error!(
+                    "vars-expand",
+                    "vars should be an object of name:value got:{}",
+                    value
+                )
+            }
+        },
+    };
+
+    let expanded = placeholder_expand(
+        &scenario,
+        &mut |name| match name.strip_prefix("env:") {
+            Some(name) => Ok(Some(env_value(name)?)),
+            None => Ok(vars.get(name).cloned()),
+        },
+        false,
+    )?;
+    value_to_jsonc(&expanded)
+}
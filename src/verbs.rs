@@ -13,6 +13,7 @@
 use crate::prelude::*;
 use afbv4::prelude::*;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::cmp::Ordering;
 use std::sync::{Arc, Condvar, Mutex};
 use std::{env, time};
@@ -66,6 +67,15 @@ extern "C" fn scenario_sort_cb(
     }
 }
 
+// optional start/exec tags filter: {"action":"exec", "tags":["smoke"]}
+fn action_get_tags(args: &AfbRqtData) -> Result<Option<Vec<String>>, AfbError> {
+    let tags = match args.get::<JsoncObj>(0)?.optional::<JsoncObj>("tags")? {
+        None => None,
+        Some(jtags) => Some(tags_from_jsonc(&jtags)?),
+    };
+    Ok(tags)
+}
+
 pub struct ScenarioReqCtx {
     _uid: &'static str,
     evt: &'static AfbEvent,
@@ -81,11 +91,12 @@ fn scenario_action_cb(
     let api = afb_rqt.get_apiv4();
     let ctx = ctx.get_mut::<ScenarioReqCtx>()?;
     let action = args.get::<&ScenarioAction>(0)?;
+    let tags = action_get_tags(args)?;
 
     match action {
         ScenarioAction::START => {
             ctx.evt.subscribe(afb_rqt)?;
-            ctx.job_id = ctx.injector.post_scenario(api, ctx.evt)?;
+            ctx.job_id = ctx.injector.post_scenario(api, ctx.evt, tags)?;
             afb_rqt.reply(ctx.job_id, 0);
         }
 
@@ -107,6 +118,7 @@ fn scenario_action_cb(
                 injector: ctx.injector,
                 event: Some(ctx.evt),
                 api,
+                tags,
             };
             job_scenario_exec(&param)?;
             let result = ctx.injector.get_result()?;
@@ -116,15 +128,124 @@ fn scenario_action_cb(
     Ok(())
 }
 
-pub type Watchdog = Arc<(Mutex<SimulationStatus>, Condvar)>;
+pub struct ScenarioMatrixRow {
+    evt: &'static AfbEvent,
+    injector: &'static Injector,
+}
+
+pub struct ScenarioMatrixCtx {
+    rows: Vec<ScenarioMatrixRow>,
+}
+
+// run or collect every parameter set of a scenario matrix
+fn matrix_action_cb(
+    afb_rqt: &AfbRequest,
+    args: &AfbRqtData,
+    ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let api = afb_rqt.get_apiv4();
+    let ctx = ctx.get_ref::<ScenarioMatrixCtx>()?;
+    let action = args.get::<&ScenarioAction>(0)?;
+    let tags = action_get_tags(args)?;
+
+    match action {
+        ScenarioAction::EXEC => {
+            for row in &ctx.rows {
+                row.evt.subscribe(afb_rqt)?;
+                let param = JobScenarioParam {
+                    injector: row.injector,
+                    event: Some(row.evt),
+                    api,
+                    tags: tags.clone(),
+                };
+                // a failing row should not prevent others to run
+                if let Err(error) = job_scenario_exec(&param) {
+                    afb_log_msg!(
+                        Warning,
+                        afb_rqt,
+                        "matrix row:{} fail error:{}",
+                        row.injector.get_uid(),
+                        error
+                    );
+                }
+            }
+        }
+        ScenarioAction::RESULT => {}
+        _ => {
+            return afb_error!(
+                "matrix-action-cb",
+                "matrix scenario only support exec|result actions, use row verbs to start/stop"
+            )
+        }
+    }
+
+    let result = JsoncObj::new();
+    for row in &ctx.rows {
+        result.add(row.injector.get_uid(), row.injector.get_result()?)?;
+    }
+    afb_rqt.reply(result, 0);
+    Ok(())
+}
+
+pub struct InjectorReply {
+    pub status: SimulationStatus,
+    pub received: Option<Value>,
+    pub replied: Option<time::Instant>,
+    // target reply status, non zero when request was rejected
+    pub code: i32,
+}
+
+pub type Watchdog = Arc<(Mutex<InjectorReply>, Condvar)>;
 
 struct InjectorAsyncCtx {
     #[allow(dead_code)]
     uid: &'static str,
     expects: JsoncObj,
+    expect_error: Option<InjectorExpectError>,
+    tolerance: MatchTolerance,
+    // captures or repeat until read the response
+    keep_received: bool,
     semaphore: Watchdog,
 }
 
+// negative expectation, pass when target rejects with expected error
+fn injector_expect_error(
+    ctx: &InjectorAsyncCtx,
+    expect: &InjectorExpectError,
+    args: &AfbRqtData,
+) -> Result<SimulationStatus, AfbError> {
+    let status = args.get_status();
+    if status == 0 {
+        return Ok(SimulationStatus::fail(
+            ctx.uid,
+            "request accepted while expecting an error".to_string(),
+        ));
+    }
+
+    if let Some(expected) = expect.status {
+        if expected != status {
+            return Ok(SimulationStatus::fail(
+                ctx.uid,
+                format!("expected error status:{} got:{}", expected, status),
+            ));
+        }
+    }
+
+    let jexpected = match &expect.error {
+        None => return Ok(SimulationStatus::Check),
+        Some(value) => value,
+    };
+    let jreceived = match args.get_count() {
+        0 => JsoncObj::new(),
+        _ => args.get::<JsoncObj>(0)?,
+    };
+    let status = match expect_match(ctx.uid, &jreceived, jexpected, &ctx.tolerance) {
+        Ok(_) => SimulationStatus::Check,
+        Err(error) => SimulationStatus::Fail(error),
+    };
+    Ok(status)
+}
+
 fn injector_async_response(
     _api: &AfbApi,
     args: &AfbRqtData,
@@ -132,8 +253,14 @@ fn injector_async_response(
 ) -> Result<(), AfbError> {
     let ctx = context.get_ref::<InjectorAsyncCtx>()?;
 
-    let status = match ctx.expects.count()? {
-        1 => {
+    let mut status = match (&ctx.expect_error, ctx.expects.count()?) {
+        (Some(expect), _) => injector_expect_error(ctx, expect, args)?,
+        // rejected request fails with or without expect, retry classifies it as an error
+        (None, _) if args.get_status() != 0 => SimulationStatus::fail(
+            ctx.uid,
+            format!("target rejected request status:{}", args.get_status()),
+        ),
+        (None, 1) => {
             // injector only use 1st expect element
             if args.get_count() < 1 {
                 return afb_error!(
@@ -144,7 +271,7 @@ fn injector_async_response(
             let jreceived = args.get::<JsoncObj>(0)?;
             let jexpected = ctx.expects.index::<JsoncObj>(0)?;
 
-            match jreceived.equal(ctx.uid, jexpected.clone(), Jequal::Partial) {
+            match expect_match(ctx.uid, &jreceived, &jexpected, &ctx.tolerance) {
                 Ok(_) => SimulationStatus::Check,
                 Err(error) => {
                     afb_log_msg!(Error, _api, "received: {}", jreceived);
@@ -153,7 +280,7 @@ fn injector_async_response(
                 }
             }
         }
-        0 => SimulationStatus::Done,
+        (None, 0) => SimulationStatus::Done,
         _ => {
             return afb_error!(
                 "injector-response-cb",
@@ -162,10 +289,31 @@ fn injector_async_response(
         }
     };
 
+    // keep received response for captures, a bad one fails but still wakes up waiter
+    let mut received = None;
+    if ctx.keep_received && args.get_count() > 0 {
+        match args
+            .get::<JsoncObj>(0)
+            .and_then(|jreceived| jsonc_to_value(&jreceived))
+        {
+            Ok(value) => received = Some(value),
+            Err(error) => status = SimulationStatus::Fail(error),
+        }
+    }
+
     let (lock, cvar) = &*ctx.semaphore;
     match lock.lock() {
+        // late reply after a timeout or a scenario stop, nobody waits for it anymore
+        Ok(value) if !matches!(value.status, SimulationStatus::Pending) => {
+            afb_log_msg!(Debug, _api, "uid:{} late reply ignored", ctx.uid);
+        }
         Ok(mut value) => {
-            *value = status;
+            *value = InjectorReply {
+                status,
+                received,
+                replied: Some(time::Instant::now()),
+                code: args.get_status(),
+            };
             cvar.notify_one();
         }
         Err(_) => {
@@ -179,20 +327,35 @@ fn injector_async_response(
     Ok(())
 }
 
-fn injector_async_request(
-    api: AfbApiV4,
+// expand captures and generators of transaction queries, add optional clock offset
+fn injector_build_query(
     transac: &InjectorEntry,
-    semaphore: Watchdog,
-) -> Result<(), AfbError> {
+    captures: &CaptureStore,
+) -> Result<AfbParams, AfbError> {
     let mut query = AfbParams::new();
     for idx in 0..transac.queries.count()? {
         let jsonc = transac.queries.index::<JsoncObj>(idx)?;
-        query.push(jsonc.clone())?;
+        let jquery = captures_expand(&jsonc, captures, &transac.generator)?;
+        query.push(transac.clock.query_offset(jquery)?)?;
     }
+    Ok(query)
+}
+
+pub fn injector_async_request(
+    api: AfbApiV4,
+    transac: &InjectorEntry,
+    captures: &CaptureStore,
+    semaphore: Watchdog,
+) -> Result<(), AfbError> {
+    let query = injector_build_query(transac, captures)?;
 
     let subcall_ctx = InjectorAsyncCtx {
         uid: transac.uid,
         expects: transac.expects.clone(),
+        expect_error: transac.expect_error.clone(),
+        tolerance: transac.tolerance,
+        keep_received: !transac.captures.is_empty()
+            || matches!(&transac.repeat, Some(repeat) if repeat.until.is_some()),
         semaphore,
     };
 
@@ -207,34 +370,238 @@ fn injector_async_request(
     Ok(())
 }
 
-// call by jobpost when injector run a scenario
+// create a smephare with condition variable to wait either timeout either async response
+pub fn injector_new_watchdog() -> Watchdog {
+    Arc::new((
+        Mutex::new(InjectorReply {
+            status: SimulationStatus::Pending,
+            received: None,
+            replied: None,
+            code: 0,
+        }),
+        Condvar::new(),
+    ))
+}
+
+struct InjectorEventCtx {
+    uid: &'static str,
+    expect: Option<JsoncObj>,
+    tolerance: MatchTolerance,
+    semaphore: Watchdog,
+}
+
+// target event received, only used when a wait_event transaction is pending
+fn injector_event_cb(
+    event: &AfbEventMsg,
+    args: &AfbRqtData,
+    context: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let ctx = context.get_ref::<InjectorEventCtx>()?;
+    let (lock, cvar) = &*ctx.semaphore;
+    let mut reply = match lock.lock() {
+        Ok(value) => value,
+        Err(_) => {
+            return afb_error!(
+                "injector-event-cb",
+                "(hoops) fail to acquire status semaphore"
+            )
+        }
+    };
+    if !matches!(reply.status, SimulationStatus::Pending) {
+        return Ok(());
+    }
+
+    let jreceived = match args.get_count() {
+        0 => JsoncObj::new(),
+        _ => args.get::<JsoncObj>(0)?,
+    };
+    if let Some(jexpected) = &ctx.expect {
+        if let Err(error) = expect_match(ctx.uid, &jreceived, jexpected, &ctx.tolerance) {
+            afb_log_msg!(
+                Debug,
+                None,
+                "uid:{} event:{} ignored {}",
+                ctx.uid,
+                event.get_name(),
+                error
+            );
+            return Ok(());
+        }
+    }
+
+    *reply = InjectorReply {
+        status: SimulationStatus::Check,
+        received: Some(jsonc_to_value(&jreceived)?),
+        replied: Some(time::Instant::now()),
+        code: 0,
+    };
+    cvar.notify_one();
+    Ok(())
+}
+
+pub fn register_event_handlers(
+    api: &mut AfbApi,
+    injector: &'static Injector,
+) -> Result<(), AfbError> {
+    let state = injector.lock_state()?;
+    for (idx, transac) in state.entries.iter().enumerate() {
+        let wait = match &transac.wait_event {
+            None => continue,
+            Some(value) => value,
+        };
+        let handler =
+            AfbEvtHandler::new(format!("{}:{}:{}", injector.get_uid(), transac.uid, idx).as_str())
+                .set_pattern(to_static_str(format!("{}/{}", wait.api, wait.event)))
+                .set_callback(injector_event_cb)
+                .set_context(InjectorEventCtx {
+                    uid: transac.uid,
+                    expect: wait.expect.clone(),
+                    tolerance: transac.tolerance,
+                    semaphore: wait.semaphore.clone(),
+                })
+                .finalize()?;
+        api.add_evt_handler(handler);
+    }
+    Ok(())
+}
+
+// arm event semaphore, optionally ask target to subscribe, then wait for a matching event
+fn injector_wait_event(
+    api: AfbApiV4,
+    transac: &InjectorEntry,
+    wait: &InjectorWaitEvent,
+    captures: &CaptureStore,
+    remaining: time::Duration,
+) -> Result<InjectorReply, AfbError> {
+    let (lock, _) = &*wait.semaphore;
+    match lock.lock() {
+        Ok(mut value) => {
+            *value = InjectorReply {
+                status: SimulationStatus::Pending,
+                received: None,
+                replied: None,
+                code: 0,
+            };
+        }
+        Err(_) => {
+            return afb_error!(
+                "injector-wait-event",
+                "(hoops) fail to acquire status semaphore"
+            )
+        }
+    }
+
+    if let Some(verb) = wait.subscribe {
+        let semaphore = injector_new_watchdog();
+        let query = injector_build_query(transac, captures)?;
+        let subcall_ctx = InjectorAsyncCtx {
+            uid: transac.uid,
+            expects: JsoncObj::array(),
+            expect_error: None,
+            tolerance: transac.tolerance,
+            keep_received: false,
+            semaphore: semaphore.clone(),
+        };
+        AfbSubCall::call_async(
+            api,
+            wait.api,
+            verb,
+            query,
+            injector_async_response,
+            subcall_ctx,
+        )?;
+        let reply = injector_wait_reply(
+            &semaphore,
+            transac.retry.timeout.min(remaining),
+            &transac.clock,
+        );
+        if !matches!(reply.status, SimulationStatus::Done) {
+            return afb_error!(
+                "injector-wait-event",
+                "uid:{} subscribe {}/{} fail status:{:?}",
+                transac.uid,
+                wait.api,
+                verb,
+                reply.status
+            );
+        }
+    }
+
+    let reply = injector_wait_reply(&wait.semaphore, wait.timeout.min(remaining), &transac.clock);
+
+    // disarm semaphore to ignore further events
+    if let Ok(mut value) = lock.lock() {
+        value.status = SimulationStatus::Skip;
+    }
+    Ok(reply)
+}
+
+// call by jobpost when injector run a scenario, remaining is time left before scenario deadline
 pub fn injector_launch_transac(
     api: AfbApiV4,
     transac: &InjectorEntry,
-) -> Result<SimulationStatus, AfbError> {
-    // create a smephare with condition variable to wait either timeout either async response
-    let semaphore = Arc::new((Mutex::new(SimulationStatus::Pending), Condvar::new()));
+    captures: &CaptureStore,
+    remaining: time::Duration,
+) -> Result<InjectorReply, AfbError> {
+    if let Some(wait) = &transac.wait_event {
+        return injector_wait_event(api, transac, wait, captures, remaining);
+    }
+
+    let semaphore = injector_new_watchdog();
     //afb_log_msg!(Debug, api, "spawning {}/{}&{}", transac.target, transac.verb, transac.queries);
 
     // start asynchronous subcall request
-    injector_async_request(api, transac, semaphore.clone())?;
+    injector_async_request(api, transac, captures, semaphore.clone())?;
+    Ok(injector_wait_reply(
+        &semaphore,
+        transac.retry.timeout.min(remaining),
+        &transac.clock,
+    ))
+}
 
-    // wait util call return or timeout burn
-    let (lock, cvar) = &*semaphore;
-    let status = match lock.lock() {
+pub fn injector_wait_reply(
+    semaphore: &Watchdog,
+    timeout: time::Duration,
+    clock: &InjectorClock,
+) -> InjectorReply {
+    // wait util call return, timeout burn or scenario stop
+    clock.arm(semaphore);
+    let (lock, cvar) = &**semaphore;
+    let reply = match lock.lock() {
         Ok(value) => {
-            //value = cvar.wait(value).unwrap();
-            let result = cvar.wait_timeout(value, transac.retry.timeout).unwrap();
-            if result.1.timed_out() {
-                SimulationStatus::Timeout
+            // response may arrive before waiting starts, only wait while still pending
+            let mut result = cvar
+                .wait_timeout_while(value, timeout, |reply| {
+                    matches!(reply.status, SimulationStatus::Pending)
+                })
+                .unwrap();
+            if let SimulationStatus::Pending = result.0.status {
+                // a late reply should not overwrite the timeout
+                result.0.status = SimulationStatus::Timeout;
+                InjectorReply {
+                    status: SimulationStatus::Timeout,
+                    received: None,
+                    replied: None,
+                    code: 0,
+                }
             } else {
-                result.0.clone()
+                InjectorReply {
+                    status: result.0.status.clone(),
+                    received: result.0.received.take(),
+                    replied: result.0.replied,
+                    code: result.0.code,
+                }
             }
         }
-        Err(_) => SimulationStatus::InvalidSequence,
+        Err(_) => InjectorReply {
+            status: SimulationStatus::InvalidSequence,
+            received: None,
+            replied: None,
+            code: 0,
+        },
     };
-
-    Ok(status)
+    clock.disarm(semaphore);
+    reply
 }
 
 // call when activating manually a specific scenario command
@@ -281,7 +648,8 @@ fn responder_req_cb(
     let received_query = args.get::<JsoncObj>(0)?;
     let expected_query = transac.queries.index::<JsoncObj>(transac.sequence)?;
 
-    match received_query.equal(transac.uid, expected_query.clone(), Jequal::Partial) {
+    let tolerance = transac.responder.get_tolerance();
+    match expect_match(transac.uid, &received_query, &expected_query, &tolerance) {
         Ok(_) => {
             let responses = transac.responses.index::<JsoncObj>(transac.sequence)?;
             if responses.len()? == 0 {
@@ -355,7 +723,24 @@ fn create_transaction_verb(
                 uid: verb,
                 verb: verb,
                 delay: time::Duration::new(0, 0),
+                delay_jitter: 0.0,
                 retry: InjectorRetryConf::default(),
+                captures: Vec::new(),
+                repeat: None,
+                wait_event: None,
+                expect_error: None,
+                tolerance: MatchTolerance::default(),
+                expect_latency: None,
+                latency: None,
+                depends_on: Vec::new(),
+                tags: Vec::new(),
+                skip: None,
+                generator: ValueGenerator::new(None),
+                clock: InjectorClock::new(ClockConf::default()),
+                iterations: 0,
+                on_success: None,
+                on_fail: None,
+                branch: None,
             };
             transaction_verb.set_context(context);
         }
@@ -374,6 +759,50 @@ fn create_transaction_verb(
     Ok(())
 }
 
+// parallel branches are registered as regular transaction verbs
+fn transactions_flatten(transactions: &JsoncObj) -> Result<JsoncObj, AfbError> {
+    let flatten = JsoncObj::array();
+    for idx in 0..transactions.count()? {
+        let transac = transactions.index::<JsoncObj>(idx)?;
+        match transac.optional::<JsoncObj>("parallel")? {
+            None => {
+                flatten.append(transac)?;
+            }
+            Some(jbranches) => {
+                for jdx in 0..jbranches.count()? {
+                    flatten.append(jbranches.index::<JsoncObj>(jdx)?)?;
+                }
+            }
+        }
+    }
+    Ok(flatten)
+}
+
+// setup, transactions, blocks and teardown in run order, each one gets its verbs
+fn scenario_transactions(jscenario: &JsoncObj) -> Result<JsoncObj, AfbError> {
+    let mut sections = Vec::new();
+    if let Some(jsetup) = jscenario.optional::<JsoncObj>("setup")? {
+        sections.push(jsetup);
+    }
+    sections.push(jscenario.get::<JsoncObj>("transactions")?);
+    if let Some(jblocks) = jscenario.optional::<JsoncObj>("blocks")? {
+        for jblock in jblocks.expand()? {
+            sections.push(jblock.obj);
+        }
+    }
+    if let Some(jteardown) = jscenario.optional::<JsoncObj>("teardown")? {
+        sections.push(jteardown);
+    }
+
+    let transactions = JsoncObj::array();
+    for section in sections {
+        for idx in 0..section.count()? {
+            transactions.append(section.index::<JsoncObj>(idx)?)?;
+        }
+    }
+    Ok(transactions)
+}
+
 fn create_transaction_group(
     transactions: JsoncObj,
     uid_scenario: &'static str,
@@ -381,15 +810,20 @@ fn create_transaction_group(
     callback: RqtCallback,
     context: TransactionVerbCtx,
     target: Option<&'static str>,
+    fragments: Option<JsoncObj>,
 ) -> Result<&'static AfbGroup, AfbError> {
     let scenario_group = AfbGroup::new(uid_scenario)
         .set_separator(":")
         .set_prefix(name_scenario);
 
+    // replace fragment includes with their transactions
+    let transactions = transactions_flatten(&fragments_expand(&transactions, fragments)?)?;
+
     // sort jsonc transaction by uid/verb to process duplicate verbs
     transactions.sort(Some(scenario_sort_cb))?;
 
     let mut previous_verb = "";
+    let mut previous_target = target;
     let mut infos = JsoncObj::array();
     let mut queries = JsoncObj::array();
     let mut responses = JsoncObj::array();
@@ -415,6 +849,15 @@ fn create_transaction_group(
 
         // build verb from transaction uid
         let current_verb = transaction_get_verb(&transac)?;
+        let current_target = match transac.optional::<&'static str>("target")? {
+            Some(value) => Some(value),
+            None => target,
+        };
+
+        // ignore wait_event transactions, they do not call any target verb
+        if transac.optional::<JsoncObj>("wait_event")?.is_some() {
+            continue;
+        }
 
         // ignore injector_only verbs as service discovery
         if transac.default("injector_only", false)? {
@@ -431,7 +874,7 @@ fn create_transaction_group(
 
         if previous_verb != current_verb {
             // if exist create previous_label verb scenario
-            if previous_verb.len() != 0 {
+            if !previous_verb.is_empty() {
                 create_transaction_verb(
                     scenario_group,
                     previous_verb,
@@ -441,7 +884,7 @@ fn create_transaction_group(
                     expects,
                     callback,
                     context,
-                    target,
+                    previous_target,
                 )?;
             }
             // prepare structure for new scenario verb
@@ -450,6 +893,7 @@ fn create_transaction_group(
             responses = JsoncObj::array();
             expects = JsoncObj::array();
             previous_verb = current_verb;
+            previous_target = current_target;
         }
 
         infos.append(transac_uid)?;
@@ -458,17 +902,19 @@ fn create_transaction_group(
         expects.append(expect)?;
     }
     // add last verb
-    create_transaction_verb(
-        scenario_group,
-        previous_verb,
-        infos,
-        queries,
-        responses,
-        expects,
-        callback,
-        context,
-        target,
-    )?;
+    if !previous_verb.is_empty() {
+        create_transaction_verb(
+            scenario_group,
+            previous_verb,
+            infos,
+            queries,
+            responses,
+            expects,
+            callback,
+            context,
+            previous_target,
+        )?;
+    }
 
     Ok(scenario_group.finalize()?)
 }
@@ -480,16 +926,25 @@ pub fn register_injector(
     scenario_actions::register()?;
     let mut injectors = Vec::new();
 
-    match config.target {
-        None => return afb_error!("register_injector", "target api SHOULD be defined"),
-        Some(value) => {
-            api.require_api(value);
-        }
-    }
-
     for idx in 0..config.scenarios.count()? {
         let jscenario = config.scenarios.index::<JsoncObj>(idx)?;
 
+        // whole scenario is ignored when its tags do not match SCENARIO_TAGS
+        let tags = match jscenario.optional::<JsoncObj>("tags")? {
+            None => Vec::new(),
+            Some(jtags) => tags_from_jsonc(&jtags)?,
+        };
+        if !tags_selected(&tags, &config.tags) {
+            afb_log_msg!(
+                Notice,
+                None,
+                "scenario:{} skipped tags:{} not selected",
+                jscenario.get::<String>("uid")?,
+                tags.join(",")
+            );
+            continue;
+        }
+
         let uid = match env::var("SCENARIO_UID") {
             Err(_) => format!("{}:{}", jscenario.get::<String>("uid")?, idx),
             Ok(value) => value,
@@ -499,57 +954,114 @@ pub fn register_injector(
         let info = jscenario.default("info", "")?;
         let prefix = jscenario.default("prefix", uid_scenario)?;
 
-        let transactions = jscenario.get::<JsoncObj>("transactions")?;
-        if !transactions.is_type(Jtype::Array) {
-            return afb_error!(
-                "simu-injector-config",
-                "transactions should be a valid array of (uid,request,expect)"
-            );
+        let jparameters = match jscenario.optional::<JsoncObj>("parameters")? {
+            None => {
+                let (injector, _) =
+                    register_one_injector(api, config, jscenario, uid_scenario, name, prefix)?;
+                injectors.push(injector);
+                continue;
+            }
+            Some(value) => value,
+        };
+
+        // one injector per parameter set, registered as uid:set-name
+        let mut rows = Vec::new();
+        for row in 0..jparameters.count()? {
+            let jrow = jparameters.index::<JsoncObj>(row)?;
+            let set_name = jrow.default::<String>("name", row.to_string())?;
+            let uid_row = to_static_str(format!("{}:{}", uid_scenario, set_name));
+            let name_row = to_static_str(format!("{}:{}", name, set_name));
+            let jscenario_row = parameters_expand(&jscenario, &jrow, config.fragments.clone())?;
+            let (injector, evt) =
+                register_one_injector(api, config, jscenario_row, uid_row, name_row, prefix)?;
+            rows.push(ScenarioMatrixRow { evt, injector });
+            injectors.push(injector);
         }
-        let scenario_timeout = jscenario.default("timeout", transactions.count()? as u64)?;
 
-        let scenario_event = AfbEvent::new(uid_scenario);
-        let scenario_verb = AfbVerb::new(uid_scenario);
-        let injector = Injector::new(
-            uid_scenario,
-            config.target,
-            prefix,
-            scenario_timeout,
-            transactions.clone(),
-            config.delay_conf,
-            config.retry_conf,
-        )?;
-        scenario_verb
+        let matrix_verb = AfbVerb::new(uid_scenario)
             .set_name(name)
             .set_info(info)
-            .set_actions("['start','stop','exec','result']")?
-            .set_callback(scenario_action_cb)
-            .set_context(ScenarioReqCtx {
-                _uid: uid_scenario,
-                job_id: 0,
-                injector,
-                evt: scenario_event,
-            });
-        api.add_verb(scenario_verb.finalize()?);
-        api.add_event(scenario_event);
-
-        // create a group by scenario with one verb per transaction
-        if transactions.count()? > 0 {
-            let transaction_group = create_transaction_group(
-                transactions,
-                uid_scenario,
-                name,
-                injector_req_cb,
-                TransactionVerbCtx::Injector(),
-                config.target,
-            )?;
-            api.add_group(transaction_group);
+            .set_actions("['exec','result']")?
+            .set_callback(matrix_action_cb)
+            .set_context(ScenarioMatrixCtx { rows })
+            .finalize()?;
+        api.add_verb(matrix_verb);
+    }
+
+    // require every distinct target api from binding, scenarios and transactions
+    let mut targets = Vec::new();
+    for injector in injectors.iter() {
+        for target in injector.get_targets()? {
+            if !targets.contains(&target) {
+                api.require_api(target);
+                targets.push(target);
+            }
         }
-        injectors.push(injector);
     }
     Ok(injectors)
 }
 
+fn register_one_injector(
+    api: &mut AfbApi,
+    config: &BindingConfig,
+    jscenario: JsoncObj,
+    uid_scenario: &'static str,
+    name: &'static str,
+    prefix: &'static str,
+) -> Result<(&'static Injector, &'static AfbEvent), AfbError> {
+    // resolve scenario vars and environment before parsing transactions
+    let jscenario = variables_expand(&jscenario, config.fragments.clone())?;
+    let info = jscenario.default("info", "")?;
+
+    let transactions = jscenario.get::<JsoncObj>("transactions")?;
+    if !transactions.is_type(Jtype::Array) {
+        return afb_error!(
+            "simu-injector-config",
+            "transactions should be a valid array of (uid,request,expect)"
+        );
+    }
+
+    // scenario target overrides binding target
+    let target = match jscenario.optional::<&'static str>("target")? {
+        Some(value) => Some(value),
+        None => config.target,
+    };
+
+    let scenario_event = AfbEvent::new(uid_scenario);
+    let scenario_verb = AfbVerb::new(uid_scenario);
+    let injector = Injector::new(uid_scenario, target, prefix, jscenario.clone(), config)?;
+    register_event_handlers(api, injector)?;
+    scenario_verb
+        .set_name(name)
+        .set_info(info)
+        .set_actions("['start','stop','exec','result']")?
+        .set_callback(scenario_action_cb)
+        .set_context(ScenarioReqCtx {
+            _uid: uid_scenario,
+            job_id: 0,
+            injector,
+            evt: scenario_event,
+        });
+    api.add_verb(scenario_verb.finalize()?);
+    api.add_event(scenario_event);
+
+    // create a group by scenario with one verb per transaction
+    let transactions = scenario_transactions(&jscenario)?;
+    if transactions.count()? > 0 {
+        let transaction_group = create_transaction_group(
+            transactions,
+            uid_scenario,
+            name,
+            injector_req_cb,
+            TransactionVerbCtx::Injector(),
+            target,
+            config.fragments.clone(),
+        )?;
+        api.add_group(transaction_group);
+    }
+    Ok((injector, scenario_event))
+}
+
 fn responder_reset_cb(
     afb_rqt: &AfbRequest,
     _args: &AfbRqtData,
@@ -562,7 +1074,7 @@ fn responder_reset_cb(
 }
 
 pub fn register_responder(api: &mut AfbApi, config: &BindingConfig) -> Result<(), AfbError> {
-    let responder = Responder::new(config.loop_reset);
+    let responder = Responder::new(config.loop_reset, config.tolerance);
     let responder_verb = AfbVerb::new("reset")
         .set_info("scenario sequence counter")
         .set_callback(responder_reset_cb)
@@ -579,26 +1091,59 @@ pub fn register_responder(api: &mut AfbApi, config: &BindingConfig) -> Result<()
         };
         let uid_scenario = to_static_str(uid);
         let name = jscenario.default::<&'static str>("name", uid_scenario)?;
-        let transactions = jscenario.get::<JsoncObj>("transactions")?;
-        if !transactions.is_type(Jtype::Array) {
-            return afb_error!(
-                "simu-injector-config",
-                "transactions should be a valid array of (uid,request,expect)"
-            );
-        }
 
-        // create a group by scenario with one verb per transaction
-        if transactions.count()? > 0 {
-            let transaction_group = create_transaction_group(
-                transactions,
-                uid_scenario,
-                name,
-                responder_req_cb,
-                TransactionVerbCtx::Responder(responder),
-                None,
-            )?;
-            api.add_group(transaction_group);
+        let jparameters = match jscenario.optional::<JsoncObj>("parameters")? {
+            None => {
+                register_one_responder(api, config, responder, jscenario, uid_scenario, name)?;
+                continue;
+            }
+            Some(value) => value,
+        };
+
+        // one group per parameter set, registered as uid:set-name
+        for row in 0..jparameters.count()? {
+            let jrow = jparameters.index::<JsoncObj>(row)?;
+            let set_name = jrow.default::<String>("name", row.to_string())?;
+            let uid_row = to_static_str(format!("{}:{}", uid_scenario, set_name));
+            let name_row = to_static_str(format!("{}:{}", name, set_name));
+            let jscenario_row = parameters_expand(&jscenario, &jrow, config.fragments.clone())?;
+            register_one_responder(api, config, responder, jscenario_row, uid_row, name_row)?;
         }
     }
     Ok(())
 }
+
+fn register_one_responder(
+    api: &mut AfbApi,
+    config: &BindingConfig,
+    responder: &'static Responder,
+    jscenario: JsoncObj,
+    uid_scenario: &'static str,
+    name: &'static str,
+) -> Result<(), AfbError> {
+    // resolve scenario vars and environment before parsing transactions
+    let jscenario = variables_expand(&jscenario, config.fragments.clone())?;
+    let transactions = jscenario.get::<JsoncObj>("transactions")?;
+    if !transactions.is_type(Jtype::Array) {
+        return afb_error!(
+            "simu-injector-config",
+            "transactions should be a valid array of (uid,request,expect)"
+        );
+    }
+
+    // create a group by scenario with one verb per transaction
+    let transactions = scenario_transactions(&jscenario)?;
+    if transactions.count()? > 0 {
+        let transaction_group = create_transaction_group(
+            transactions,
+            uid_scenario,
+            name,
+            responder_req_cb,
+            TransactionVerbCtx::Responder(responder),
+            None,
+            config.fragments.clone(),
+        )?;
+        api.add_group(transaction_group);
+    }
+    Ok(())
+}